@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::io::BufReader;
 use std::path::Path;
@@ -6,8 +7,11 @@ use std::path::PathBuf;
 use indexmap::IndexMap;
 use serde::Deserialize;
 use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
 
 use crate::error::Error;
+use crate::weapon::WeaponStats;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Addon {
@@ -21,11 +25,11 @@ pub struct Addon {
     pub dependencies: Vec<String>,
     /// A collection of maps by their internal name, the first map has precedence
     pub maps: IndexMap<String, PathBuf>,
-    /// A collection of models by their internal name
+    /// A collection of models by their internal name, kept in order so every machine numbers them the same
     ///
     /// Supported file types are:
     /// - `obj`: An open file format without support for animation
-    pub models: HashMap<String, PathBuf>,
+    pub models: IndexMap<String, PathBuf>,
     /// A collection of textures by their internal name
     ///
     /// Supported file types are:
@@ -34,13 +38,211 @@ pub struct Addon {
     /// - `gif`
     /// - `bmp`
     pub textures: HashMap<String, PathBuf>,
+    /// The internal name of the texture each model is drawn with, by the model's internal name
+    #[serde(default)]
+    pub model_textures: HashMap<String, String>,
+    /// A collection of weapons by their internal name
+    #[serde(default)]
+    pub weapons: HashMap<String, WeaponStats>,
+    /// The internal name of the weapon players start with
+    #[serde(default)]
+    pub starting_weapon: Option<String>,
 }
 
 impl Addon {
     pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
         let file = std::fs::File::open(path)?;
         let reader = BufReader::new(file);
-        let addon = serde_json::from_reader(reader)?;
+        let addon: Self = serde_json::from_reader(reader)?;
+
+        for (name, weapon) in &addon.weapons {
+            if let Some(problem) = weapon.problem() {
+                return Err(Error::InvalidWeapon(name.clone(), problem));
+            }
+        }
+
         Ok(addon)
     }
+
+    /// Loads the named addon and every addon it depends on from `addons_dir`
+    ///
+    /// Addons are returned in load order, so dependencies always come before their dependents
+    pub fn load_with_dependencies<P: AsRef<Path>>(
+        addons_dir: P,
+        name: &str,
+    ) -> Result<Vec<LoadedAddon>, Error> {
+        let mut stack = vec![];
+        let mut loaded = vec![];
+        visit(addons_dir.as_ref(), name, &mut stack, &mut loaded)?;
+        Ok(loaded)
+    }
+}
+
+#[derive(Debug)]
+pub struct LoadedAddon {
+    /// The internal name of the addon, matching its directory in `addons`
+    pub name: String,
+    /// The directory the addon's content paths are relative to
+    pub dir: PathBuf,
+    pub addon: Addon,
+}
+
+/// The content of a chain of addons, with later addons overriding earlier ones by internal name
+#[derive(Debug, Default)]
+pub struct Content {
+    /// The map to load on startup, taken from the last addon that declares any maps
+    pub start_map: Option<String>,
+    pub maps: IndexMap<String, PathBuf>,
+    /// Models in load order, their position is the index `components::Model` refers to them by
+    pub models: IndexMap<String, PathBuf>,
+    pub textures: HashMap<String, PathBuf>,
+    pub model_textures: HashMap<String, String>,
+    pub weapons: HashMap<String, WeaponStats>,
+    /// The weapon players start with, taken from the last addon that declares one
+    pub starting_weapon: Option<String>,
+}
+
+impl Content {
+    pub fn from_addons(addons: &[LoadedAddon]) -> Self {
+        let mut content = Self::default();
+
+        for loaded in addons {
+            if let Some(name) = loaded.addon.maps.keys().next() {
+                content.start_map = Some(name.clone());
+            }
+
+            for (name, path) in &loaded.addon.maps {
+                content.maps.insert(name.clone(), loaded.dir.join(path));
+            }
+
+            for (name, path) in &loaded.addon.models {
+                content.models.insert(name.clone(), loaded.dir.join(path));
+            }
+
+            for (name, path) in &loaded.addon.textures {
+                content.textures.insert(name.clone(), loaded.dir.join(path));
+            }
+
+            for (model, texture) in &loaded.addon.model_textures {
+                content.model_textures.insert(model.clone(), texture.clone());
+            }
+
+            for (name, weapon) in &loaded.addon.weapons {
+                content.weapons.insert(name.clone(), weapon.clone());
+            }
+
+            if let Some(weapon) = &loaded.addon.starting_weapon {
+                content.starting_weapon = Some(weapon.clone());
+            }
+        }
+
+        content
+    }
+}
+
+/// Everything two instances of the game need to agree on before playing together
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Manifest {
+    /// The version of every loaded addon by its internal name
+    pub addons: BTreeMap<String, u32>,
+    /// A hash of every content file, keyed by its kind and internal name, e.g. `model/zombie`
+    ///
+    /// Each addon's own JSON is included as `addon/<name>`, so weapon stats and model order have to match too
+    pub files: BTreeMap<String, [u8; 32]>,
+}
+
+impl Manifest {
+    pub fn new(addons: &[LoadedAddon], content: &Content) -> Result<Self, Error> {
+        let mut files = BTreeMap::new();
+        for loaded in addons {
+            let hash = Sha256::digest(std::fs::read(loaded.dir.join(format!("{}.json", loaded.name)))?);
+            files.insert(format!("addon/{}", loaded.name), hash.into());
+        }
+
+        let addons = addons
+            .iter()
+            .map(|loaded| (loaded.name.clone(), loaded.addon.version))
+            .collect();
+
+        let paths = content.maps.iter().map(|(name, path)| ("map", name, path))
+            .chain(content.models.iter().map(|(name, path)| ("model", name, path)))
+            .chain(content.textures.iter().map(|(name, path)| ("texture", name, path)));
+
+        for (kind, name, path) in paths {
+            let hash = Sha256::digest(std::fs::read(path)?);
+            files.insert(format!("{kind}/{name}"), hash.into());
+        }
+
+        Ok(Self { addons, files })
+    }
+
+    /// Describes every way `remote` differs from this manifest, empty if they match
+    pub fn mismatches(&self, remote: &Manifest) -> Vec<String> {
+        let mut mismatches = vec![];
+
+        for (name, version) in &self.addons {
+            match remote.addons.get(name) {
+                Some(remote_version) if remote_version == version => (),
+                Some(remote_version) => {
+                    let mismatch = format!("addon `{name}` is version {version} locally but {remote_version} remotely");
+                    mismatches.push(mismatch);
+                },
+                None => mismatches.push(format!("addon `{name}` is only loaded locally")),
+            }
+        }
+
+        for name in remote.addons.keys().filter(|name| !self.addons.contains_key(*name)) {
+            mismatches.push(format!("addon `{name}` is only loaded remotely"));
+        }
+
+        for (name, hash) in &self.files {
+            match remote.files.get(name) {
+                Some(remote_hash) if remote_hash == hash => (),
+                Some(_) => mismatches.push(format!("{name} differs")),
+                None => mismatches.push(format!("{name} only exists locally")),
+            }
+        }
+
+        for name in remote.files.keys().filter(|name| !self.files.contains_key(*name)) {
+            mismatches.push(format!("{name} only exists remotely"));
+        }
+
+        mismatches
+    }
+}
+
+// Depth first traversal of the dependency graph, `stack` holds the chain currently being resolved
+fn visit(
+    addons_dir: &Path,
+    name: &str,
+    stack: &mut Vec<String>,
+    loaded: &mut Vec<LoadedAddon>,
+) -> Result<(), Error> {
+    if loaded.iter().any(|addon| addon.name == name) {
+        return Ok(());
+    }
+
+    if let Some(i) = stack.iter().position(|n| n == name) {
+        let mut cycle = stack[i..].to_vec();
+        cycle.push(name.to_string());
+        return Err(Error::AddonDependencyCycle(cycle));
+    }
+
+    let dir = addons_dir.join(name);
+    let path = dir.join(format!("{name}.json"));
+    if !path.is_file() {
+        return Err(Error::AddonNotFound(name.to_string()));
+    }
+
+    let addon = Addon::from_path(&path)?;
+
+    stack.push(name.to_string());
+    for dependency in &addon.dependencies {
+        visit(addons_dir, dependency, stack, loaded)?;
+    }
+    stack.pop();
+
+    loaded.push(LoadedAddon { name: name.to_string(), dir, addon });
+
+    Ok(())
 }
@@ -63,3 +63,7 @@ impl Default for Camera {
         }
     }
 }
+
+/// A camera for each local player, indexed by `LocalPlayer`
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Cameras(pub Vec<Camera>);
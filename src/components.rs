@@ -1,17 +1,95 @@
+use legion::Entity;
 use nalgebra::Point3;
 use nalgebra::UnitQuaternion;
 use nalgebra::Vector3;
+use parry3d::shape::SharedShape;
 use winit::dpi::PhysicalSize;
 
+use crate::weapon::WeaponStats;
+
+/// Controls how quickly an entity can change direction while airborne
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AirControl(pub f32);
+
+/// The shape an entity collides with the map as, relative to its `Position`
+#[derive(Clone, Debug)]
+pub struct Collider(pub SharedShape);
+
+/// The damage an entity deals to whatever it hits
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Damage(pub f32);
+
+/// Marks an entity whose health has run out but is left in the world
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Dead;
+
+/// The downwards acceleration applied to an entity
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Gravity(pub f32);
+
+/// Whether an entity is standing on the ground, updated when it collides with the map
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Grounded(pub bool);
+
+/// The health an entity has left, it dies once this reaches zero
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Health(pub f32);
+
+/// The upwards speed an entity leaves the ground with when jumping
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct JumpSpeed(pub f32);
+
+/// Marks a player controlled on this machine, by their index among the local players
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct LocalPlayer(pub usize);
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct Model(pub u32);
 
+/// Identifies an entity across the network, assigned by the server
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct NetworkId(pub u32);
+
+/// What happens to an entity when it dies, entities without this are despawned
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum OnDeath {
+    /// Remove the entity from the world
+    Despawn,
+    /// Leave the entity in the world, tagged as `Dead`
+    Ragdoll,
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct PlayerBrain;
 
+/// Marks a place players may be spawned at, placed by `player_spawn` map entities
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PlayerSpawn;
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Position(pub Point3<f32>);
 
+/// Where an entity was on the previous tick, for drawing it between ticks
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PreviousPosition(pub Point3<f32>);
+
+/// How an entity was facing on the previous tick, for drawing it between ticks
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PreviousRotation(pub UnitQuaternion<f32>);
+
+/// A shot in flight, removed when it hits something or its lifetime runs out
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Projectile {
+    /// The entity that fired the projectile
+    pub owner: Entity,
+    /// Seconds left before the projectile is removed
+    pub lifetime: f32,
+}
+
+/// Marks a player whose movement is simulated as their client's input arrives, rather than by the schedule
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RemoteInput;
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct Resolution {
     pub width: u32,
@@ -42,5 +120,38 @@ pub struct Rotation(pub UnitQuaternion<f32>);
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Speed(pub f32);
 
+/// A volume in world space that reacts to players entering it
+#[derive(Clone, Debug)]
+pub struct Trigger(pub SharedShape);
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct Velocity(pub Vector3<f32>);
+
+/// Sends players that enter its `Trigger` to another map
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct WarpZone {
+    /// The internal name of the map to load
+    pub destination: String,
+    /// Whether players have to press use inside the trigger rather than just walking in
+    pub requires_use: bool,
+}
+
+/// The weapon an entity fires
+#[derive(Clone, Debug, PartialEq)]
+pub struct Weapon {
+    pub stats: WeaponStats,
+    /// The model projectiles fired by this weapon are drawn with, if any
+    pub projectile_model: Option<Model>,
+    /// Seconds until the weapon can fire again, or finishes reloading
+    pub cooldown: f32,
+    /// Shots left in the magazine, unused by weapons without one
+    pub ammo: u32,
+}
+
+/// Drives an entity to chase down the nearest player
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ZombieBrain;
+
+/// Marks a place zombies may be spawned at, placed by `zombie_spawn` map entities
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ZombieSpawn;
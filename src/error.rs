@@ -2,11 +2,20 @@ use std::fmt::Display;
 
 #[derive(Debug)]
 pub enum Error {
+    AddonDependencyCycle(Vec<String>),
+    AddonNotFound(String),
+    BincodeError(bincode::Error),
+    ContentMismatch(Vec<String>),
     GamepadError(gilrs::Error),
     ImageError(image::ImageError),
+    InvalidSettingOverride(String),
+    InvalidTickRate(u32),
+    InvalidWeapon(String, &'static str),
     IOError(std::io::Error),
     JsonError(serde_json::Error),
     MapError(mappy::Error),
+    MapNotFound(String),
+    MapWithoutGeometry,
     MeshWithoutNormals,
     MeshWithoutTexCoords,
     NoDocumentDirectory,
@@ -19,11 +28,24 @@ pub enum Error {
 impl<'a> Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
+            Error::AddonDependencyCycle(cycle) => write!(f, "Addon dependency cycle: {}", cycle.join(" -> ")),
+            Error::AddonNotFound(name) => write!(f, "Could not find the addon `{name}`"),
+            Error::BincodeError(e) => e.fmt(f),
+            Error::ContentMismatch(mismatches) => {
+                write!(f, "Content does not match: {}", mismatches.join(", "))
+            },
             Error::GamepadError(e) => e.fmt(f),
             Error::ImageError(e) => e.fmt(f),
+            Error::InvalidSettingOverride(setting) => {
+                write!(f, "Invalid setting `{setting}`, expected a known key such as `video.fov=100`")
+            },
+            Error::InvalidTickRate(tick_rate) => write!(f, "Invalid tick rate {tick_rate}, expected at least 1"),
+            Error::InvalidWeapon(name, problem) => write!(f, "Invalid weapon `{name}`, {problem}"),
             Error::IOError(e) => e.fmt(f),
             Error::JsonError(e) => e.fmt(f),
             Error::MapError(e) => e.fmt(f),
+            Error::MapNotFound(name) => write!(f, "Could not find the map `{name}`"),
+            Error::MapWithoutGeometry => write!(f, "Attempted to load a map without any surfaces"),
             Error::MeshWithoutNormals => write!(f, "Attempted to load a mesh without normals"),
             Error::MeshWithoutTexCoords => write!(f, "Attempted to load a mesh without tex_coords"),
             Error::NoDocumentDirectory => write!(f, "Could not find the user document directory"),
@@ -35,6 +57,12 @@ impl<'a> Display for Error {
     }
 }
 
+impl From<bincode::Error> for Error {
+    fn from(from: bincode::Error) -> Self {
+        Self::BincodeError(from)
+    }
+}
+
 impl From<gilrs::Error> for Error {
     fn from(from: gilrs::Error) -> Self {
         Self::GamepadError(from)
@@ -0,0 +1,48 @@
+use legion::Entity;
+
+/// A queue of events that systems write into and later systems consume
+#[derive(Debug)]
+pub struct Events<T> {
+    events: Vec<T>,
+}
+
+impl<T> Events<T> {
+    pub fn send(&mut self, event: T) {
+        self.events.push(event);
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, T> {
+        self.events.iter()
+    }
+
+    pub fn drain(&mut self) -> std::vec::Drain<'_, T> {
+        self.events.drain(..)
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self { events: vec![] }
+    }
+}
+
+/// Damage to be dealt to an entity with `Health`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DamageEvent {
+    pub target: Entity,
+    pub amount: f32,
+    /// The entity responsible for the damage, if any
+    pub source: Option<Entity>,
+}
+
+/// Sent once for every entity whose `Health` runs out, lasting until the next damage is applied
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DeathEvent {
+    pub entity: Entity,
+    /// The entity responsible for the killing blow, if any
+    pub killer: Option<Entity>,
+}
@@ -1,84 +1,92 @@
 use mappy::Map;
 use rendering_util::RenderingContext;
-use tokio::sync::mpsc::UnboundedReceiver;
 use wgpu::Buffer;
+use wgpu::BufferAddress;
+use wgpu::BufferDescriptor;
 use wgpu::BufferUsages;
 use wgpu::Device;
 use wgpu::Extent3d;
+use wgpu::PresentMode;
 use wgpu::Texture;
 use wgpu::TextureDescriptor;
 use wgpu::TextureDimension;
-use wgpu::TextureFormat;
 use wgpu::TextureUsages;
 use wgpu::TextureView;
 use wgpu::TextureViewDescriptor;
-use wgpu::util::BufferInitDescriptor;
-use wgpu::util::DeviceExt;
 use winit::window::Window;
 
 use crate::camera::Camera;
 use crate::components::Resolution;
 use crate::error::Error;
+use crate::input::MAX_LOCAL_PLAYERS;
 
 use super::DEPTH_FORMAT;
 use super::Globals;
-use super::Instance;
 use super::MapRenderer;
 use super::Model;
 use super::ModelRenderer;
+use super::RenderList;
+use super::Viewport;
 
 pub struct Graphics {
     rendering_context: RenderingContext,
-    instance_receiver: UnboundedReceiver<Instance>,
     depth_stencil: Texture,
     depth_stencil_view: TextureView,
+    /// The globals for every viewport, each `globals_stride` bytes apart
     globals: Buffer,
+    globals_stride: u32,
     map_renderer: MapRenderer,
     model_renderer: ModelRenderer,
-    textures: Vec<Texture>,
-    texture_views: Vec<TextureView>,
 }
 
 impl Graphics {
     pub async fn new(
         window: &Window,
         resolution: Resolution,
+        present_mode: PresentMode,
         map: &Map<'_>,
         models: &[Model],
-        instance_receiver: UnboundedReceiver<Instance>,
+        textures: &[super::Texture],
     ) -> Result<Self, Error> {
         let width = resolution.width;
         let height = resolution.height;
 
-        let rc = RenderingContext::new(&window, width, height).await?;
+        let mut rc = RenderingContext::new(&window, width, height).await?;
+        rc.set_present_mode(present_mode);
 
         let (depth_stencil, depth_stencil_view) = create_depth_stencil(&rc.device, width, height);
 
-        let globals = rc.device.create_buffer_init(&BufferInitDescriptor {
+        // Dynamic offsets have to land on the device's alignment
+        let alignment = rc.device.limits().min_uniform_buffer_offset_alignment;
+        let globals_stride = (std::mem::size_of::<Globals>() as u32 + alignment - 1) / alignment * alignment;
+        let globals = rc.device.create_buffer(&BufferDescriptor {
             label: Some("globals"),
-            contents: bytemuck::bytes_of(
-                &Globals::from_camera(&Camera::default(), width, height)
-            ),
+            size: (globals_stride as usize * MAX_LOCAL_PLAYERS) as BufferAddress,
             usage: BufferUsages::COPY_DST | BufferUsages::UNIFORM,
+            mapped_at_creation: false,
         });
 
         let map_renderer = MapRenderer::new(&rc, &globals, &map);
-        let model_renderer = ModelRenderer::new(&rc, &globals, models);
+        let model_renderer = ModelRenderer::new(&rc, &globals, models, textures);
 
         Ok(Self {
             rendering_context: rc,
-            instance_receiver,
             depth_stencil,
             depth_stencil_view,
             globals,
+            globals_stride,
             map_renderer,
             model_renderer,
-            textures: vec![],
-            texture_views: vec![],
         })
     }
 
-    pub fn render(&mut self, resolution: Resolution, camera: &Camera) -> Result<(), Error> {
+    /// Draws the world and everything in the render list once per camera, splitting the screen between them
+    pub fn render(
+        &mut self,
+        resolution: Resolution,
+        cameras: &[Camera],
+        render_list: &RenderList,
+    ) -> Result<(), Error> {
         let width = resolution.width;
         let height = resolution.height;
 
@@ -90,48 +98,35 @@ impl Graphics {
             self.depth_stencil_view = dsv;
         }
 
-        // Write our globals
-        rc.queue.write_buffer(
-            &self.globals,
-            0,
-            bytemuck::bytes_of(&Globals::from_camera(camera, width, height),
-        ));
+        // Write our globals for each viewport
+        let cameras = &cameras[..cameras.len().min(MAX_LOCAL_PLAYERS)];
+        let viewports = Viewport::layout(resolution, cameras.len(), self.globals_stride);
+        for (camera, viewport) in cameras.iter().zip(&viewports) {
+            rc.queue.write_buffer(
+                &self.globals,
+                viewport.globals_offset as BufferAddress,
+                bytemuck::bytes_of(&Globals::from_camera(camera, viewport.width, viewport.height)),
+            );
+        }
+        let viewports = &viewports[..cameras.len()];
 
         // Do our rendering
         self.rendering_context.render(width, height, |rc, surface_view| {
-            self.map_renderer.render(rc, surface_view, &self.depth_stencil_view);
-
-            let mut instances = vec![];
-            while let Ok(instance) = self.instance_receiver.try_recv() {
-                instances.push(instance);
-            }
-
-            self.model_renderer.render(rc, surface_view, &self.depth_stencil_view, &instances);
+            self.map_renderer.render(rc, surface_view, &self.depth_stencil_view, viewports);
+            self.model_renderer.render(rc, surface_view, &self.depth_stencil_view, viewports, render_list);
         })?;
 
         Ok(())
     }
 
-    pub fn load_texture(&mut self, texture: &super::Texture) {
-        let rc = &self.rendering_context;
-        let texture = rc.device.create_texture(&TextureDescriptor {
-            label: None,
-            size: Extent3d {
-                width: texture.resolution.width,
-                height: texture.resolution.height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: TextureDimension::D2,
-            format: TextureFormat::Rgba8UnormSrgb,
-            usage: TextureUsages::TEXTURE_BINDING,
-        });
-
-        let view = texture.create_view(&TextureViewDescriptor::default());
+    /// Changes how frames are presented, taking effect from the next frame
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) {
+        self.rendering_context.set_present_mode(present_mode);
+    }
 
-        self.textures.push(texture);
-        self.texture_views.push(view);
+    /// Replaces the map being drawn
+    pub fn load_map(&mut self, map: &Map<'_>) {
+        self.map_renderer = MapRenderer::new(&self.rendering_context, &self.globals, map);
     }
 }
 
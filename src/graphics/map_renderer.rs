@@ -34,6 +34,7 @@ use wgpu::PipelineLayoutDescriptor;
 use wgpu::PolygonMode;
 use wgpu::PrimitiveState;
 use wgpu::PrimitiveTopology;
+use wgpu::RenderPass;
 use wgpu::RenderPassColorAttachment;
 use wgpu::RenderPassDepthStencilAttachment;
 use wgpu::RenderPassDescriptor;
@@ -53,6 +54,7 @@ use wgpu::vertex_attr_array;
 
 use super::DEPTH_FORMAT;
 use super::Globals;
+use super::Viewport;
 
 #[allow(dead_code)]
 pub struct MapRenderer {
@@ -78,7 +80,7 @@ impl MapRenderer {
                     visibility: ShaderStages::VERTEX,
                     ty: BindingType::Buffer {
                         ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
+                        has_dynamic_offset: true,
                         min_binding_size: BufferSize::new(std::mem::size_of::<Globals>() as _),
                     },
                     count: None,
@@ -162,7 +164,11 @@ impl MapRenderer {
             entries: &[
                 BindGroupEntry {
                     binding: 0,
-                    resource: globals.as_entire_binding(),
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: globals,
+                        offset: 0,
+                        size: BufferSize::new(std::mem::size_of::<Globals>() as u64),
+                    }),
                 },
                 BindGroupEntry {
                     binding: 1,
@@ -192,6 +198,7 @@ impl MapRenderer {
         rc: &RenderingContext,
         surface_view: &TextureView,
         depth_stencil_view: &TextureView,
+        viewports: &[Viewport],
     ) {
         // Build our command encoder
         let mut command_encoder = rc.device.create_command_encoder(&CommandEncoderDescriptor {
@@ -222,12 +229,17 @@ impl MapRenderer {
 
             render_pass.set_pipeline(&self.pipeline);
             render_pass.set_vertex_buffer(0, self.vertices.slice(..));
-            let mut start = 0;
-            for i in 0..self.vertex_counts.len() {
-                render_pass.set_bind_group(0, &self.bind_group, &[(i * std::mem::size_of::<SurfaceInfo>()) as u32]);
-                let vertex_count = self.vertex_counts[i];
-                render_pass.draw(start..start + vertex_count, 0..1);
-                start += vertex_count;
+            for viewport in viewports {
+                set_viewport(&mut render_pass, viewport);
+
+                let mut start = 0;
+                for i in 0..self.vertex_counts.len() {
+                    let offsets = [viewport.globals_offset, (i * std::mem::size_of::<SurfaceInfo>()) as u32];
+                    render_pass.set_bind_group(0, &self.bind_group, &offsets);
+                    let vertex_count = self.vertex_counts[i];
+                    render_pass.draw(start..start + vertex_count, 0..1);
+                    start += vertex_count;
+                }
             }
         }
 
@@ -235,3 +247,15 @@ impl MapRenderer {
         rc.queue.submit([command_encoder.finish()]);
     }
 }
+
+// Helper function for restricting drawing to a viewport, shared with the other renderers
+pub(super) fn set_viewport(render_pass: &mut RenderPass<'_>, viewport: &Viewport) {
+    render_pass.set_viewport(
+        viewport.x as f32,
+        viewport.y as f32,
+        viewport.width as f32,
+        viewport.height as f32,
+        0.0,
+        1.0,
+    );
+}
@@ -4,11 +4,14 @@ mod instance;
 mod map_renderer;
 mod model;
 mod model_renderer;
+mod render_list;
 mod texture;
+mod viewport;
 
 pub use self::graphics::Graphics;
 pub use self::instance::Instance;
 pub use self::model::Model;
+pub use self::render_list::RenderList;
 pub use self::texture::Texture;
 
 use wgpu::TextureFormat;
@@ -17,5 +20,6 @@ use self::globals::Globals;
 use self::map_renderer::MapRenderer;
 use self::model::Vertex;
 use self::model_renderer::ModelRenderer;
+use self::viewport::Viewport;
 
 pub const DEPTH_FORMAT: TextureFormat = TextureFormat::Depth32Float;
@@ -43,7 +43,7 @@ impl Vertex {
                     shader_location: 1,
                 },
                 VertexAttribute {
-                    format: VertexFormat::Float32x3,
+                    format: VertexFormat::Float32x2,
                     offset: std::mem::size_of::<[f32; 6]>() as BufferAddress,
                     shader_location: 2,
                 },
@@ -61,6 +61,8 @@ pub struct Mesh {
 #[derive(Clone, Debug)]
 pub struct Model {
     pub meshes: Vec<Mesh>,
+    /// The index of the texture the model is drawn with, if any
+    pub texture: Option<usize>,
 }
 
 impl Model {
@@ -93,9 +95,10 @@ impl Model {
                         model.mesh.normals[i * 3 + 1],
                         model.mesh.normals[i * 3 + 2]
                     ]),
+                    // Obj puts the origin of its texture coordinates at the bottom left, we want the top left
                     tex_coord: point![
                         model.mesh.texcoords[i * 2],
-                        model.mesh.texcoords[i * 2 + 1]
+                        1.0 - model.mesh.texcoords[i * 2 + 1]
                     ],
                 });
             }
@@ -108,6 +111,7 @@ impl Model {
 
         Ok(Self {
             meshes,
+            texture: None,
         })
     }
 }
@@ -1,13 +1,20 @@
+use std::num::NonZeroU32;
+use std::ops::Range;
+
 use rendering_util::RenderingContext;
+use wgpu::AddressMode;
 use wgpu::BindGroup;
 use wgpu::BindGroupDescriptor;
 use wgpu::BindGroupEntry;
 use wgpu::BindGroupLayout;
 use wgpu::BindGroupLayoutDescriptor;
 use wgpu::BindGroupLayoutEntry;
+use wgpu::BindingResource;
 use wgpu::BindingType;
 use wgpu::BlendState;
 use wgpu::Buffer;
+use wgpu::BufferAddress;
+use wgpu::BufferBinding;
 use wgpu::BufferBindingType;
 use wgpu::BufferDescriptor;
 use wgpu::BufferSize;
@@ -18,13 +25,18 @@ use wgpu::CommandEncoderDescriptor;
 use wgpu::CompareFunction;
 use wgpu::DepthBiasState;
 use wgpu::DepthStencilState;
+use wgpu::Extent3d;
 use wgpu::Face;
 use wgpu::FragmentState;
+use wgpu::FilterMode;
 use wgpu::FrontFace;
+use wgpu::ImageCopyTexture;
+use wgpu::ImageDataLayout;
 use wgpu::IndexFormat;
 use wgpu::LoadOp;
 use wgpu::MultisampleState;
 use wgpu::Operations;
+use wgpu::Origin3d;
 use wgpu::PipelineLayout;
 use wgpu::PipelineLayoutDescriptor;
 use wgpu::PolygonMode;
@@ -35,10 +47,22 @@ use wgpu::RenderPassDepthStencilAttachment;
 use wgpu::RenderPassDescriptor;
 use wgpu::RenderPipeline;
 use wgpu::RenderPipelineDescriptor;
+use wgpu::Sampler;
+use wgpu::SamplerBindingType;
+use wgpu::SamplerDescriptor;
 use wgpu::ShaderModule;
 use wgpu::ShaderStages;
 use wgpu::StencilState;
+use wgpu::Texture;
+use wgpu::TextureAspect;
+use wgpu::TextureDescriptor;
+use wgpu::TextureDimension;
+use wgpu::TextureFormat;
+use wgpu::TextureSampleType;
+use wgpu::TextureUsages;
 use wgpu::TextureView;
+use wgpu::TextureViewDescriptor;
+use wgpu::TextureViewDimension;
 use wgpu::VertexState;
 use wgpu::include_wgsl;
 
@@ -46,7 +70,18 @@ use super::DEPTH_FORMAT;
 use super::Globals;
 use super::Instance;
 use super::Model;
+use super::RenderList;
 use super::Vertex;
+use super::Viewport;
+use super::map_renderer::set_viewport;
+
+/// Where a model's meshes sit in the shared vertex and index buffers
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct ModelRange {
+    /// The model's first vertex, which its indices are relative to
+    base_vertex: i32,
+    indices: Range<u32>,
+}
 
 #[allow(dead_code)]
 pub struct ModelRenderer {
@@ -58,11 +93,20 @@ pub struct ModelRenderer {
     instances_len: usize,
     instances: Buffer,
     indices: Buffer,
+    /// Where each model is in `vertices` and `indices`, by model index
+    model_ranges: Vec<ModelRange>,
     bind_group: BindGroup,
+    texture_bind_group_layout: BindGroupLayout,
+    sampler: Sampler,
+    textures: Vec<Texture>,
+    /// A bind group for each texture, followed by one for the white texture untextured models use
+    texture_bind_groups: Vec<BindGroup>,
+    /// The index into `texture_bind_groups` each model is drawn with
+    model_textures: Vec<usize>,
 }
 
 impl ModelRenderer {
-    pub fn new(rc: &RenderingContext, globals: &Buffer, models: &[Model]) -> Self {
+    pub fn new(rc: &RenderingContext, globals: &Buffer, models: &[Model], textures: &[super::Texture]) -> Self {
         let shader = rc.device.create_shader_module(&include_wgsl!("shaders/model.wgsl"));
 
         let bind_group_layout = rc.device.create_bind_group_layout(&BindGroupLayoutDescriptor {
@@ -73,7 +117,7 @@ impl ModelRenderer {
                     visibility: ShaderStages::VERTEX,
                     ty: BindingType::Buffer {
                         ty: BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
+                        has_dynamic_offset: true,
                         min_binding_size: BufferSize::new(std::mem::size_of::<Globals>() as _),
                     },
                     count: None,
@@ -81,9 +125,31 @@ impl ModelRenderer {
             ]
         });
 
+        let texture_bind_group_layout = rc.device.create_bind_group_layout(&BindGroupLayoutDescriptor {
+            label: Some("ModelRenderer::texture_bind_group_layout"),
+            entries: &[
+                BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Texture {
+                        sample_type: TextureSampleType::Float { filterable: true },
+                        view_dimension: TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: ShaderStages::FRAGMENT,
+                    ty: BindingType::Sampler(SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ]
+        });
+
         let pipeline_layout = rc.device.create_pipeline_layout(&PipelineLayoutDescriptor {
             label: Some("ModelRenderer::pipeline_layout"),
-            bind_group_layouts: &[&bind_group_layout],
+            bind_group_layouts: &[&bind_group_layout, &texture_bind_group_layout],
             push_constant_ranges: &[],
         });
 
@@ -120,7 +186,7 @@ impl ModelRenderer {
                 entry_point: "fs_main",
                 targets: &[ColorTargetState {
                     format: rc.surface_format(),
-                    blend: Some(BlendState::ALPHA_BLENDING),
+                    blend: Some(BlendState::REPLACE),
                     write_mask: ColorWrites::ALL,
                 }],
             }),
@@ -156,11 +222,36 @@ impl ModelRenderer {
             mapped_at_creation: false,
         });
 
+        // Pack every mesh in one after another, keeping each model's meshes together so it's a single draw
+        let mut model_ranges = vec![];
+        let mut vertex_offset = 0;
+        let mut index_offset = 0;
         for model in models {
+            let base_vertex = vertex_offset;
+            let first_index = index_offset;
+
             for mesh in &model.meshes {
-                rc.queue.write_buffer(&vertices, 0, bytemuck::cast_slice(&mesh.vertices));
-                rc.queue.write_buffer(&indices, 0, bytemuck::cast_slice(&mesh.indices));
+                let mesh_indices: Vec<u32> = mesh.indices
+                    .iter()
+                    .map(|index| index + vertex_offset - base_vertex)
+                    .collect();
+
+                rc.queue.write_buffer(
+                    &vertices,
+                    (std::mem::size_of::<Vertex>() * vertex_offset as usize) as BufferAddress,
+                    bytemuck::cast_slice(&mesh.vertices),
+                );
+                rc.queue.write_buffer(
+                    &indices,
+                    (std::mem::size_of::<u32>() * index_offset as usize) as BufferAddress,
+                    bytemuck::cast_slice(&mesh_indices),
+                );
+
+                vertex_offset += mesh.vertices.len() as u32;
+                index_offset += mesh.indices.len() as u32;
             }
+
+            model_ranges.push(ModelRange { base_vertex: base_vertex as i32, indices: first_index..index_offset });
         }
 
         let bind_group = rc.device.create_bind_group(&BindGroupDescriptor {
@@ -169,11 +260,54 @@ impl ModelRenderer {
             entries: &[
                 BindGroupEntry {
                     binding: 0,
-                    resource: globals.as_entire_binding(),
+                    resource: BindingResource::Buffer(BufferBinding {
+                        buffer: globals,
+                        offset: 0,
+                        size: BufferSize::new(std::mem::size_of::<Globals>() as u64),
+                    }),
                 },
             ],
         });
 
+        let sampler = rc.device.create_sampler(&SamplerDescriptor {
+            label: Some("ModelRenderer::sampler"),
+            address_mode_u: AddressMode::Repeat,
+            address_mode_v: AddressMode::Repeat,
+            address_mode_w: AddressMode::Repeat,
+            mag_filter: FilterMode::Linear,
+            min_filter: FilterMode::Linear,
+            mipmap_filter: FilterMode::Linear,
+            ..Default::default()
+        });
+
+        // Upload every texture, with the white texture last
+        let white = super::Texture::white();
+        let mut gpu_textures = vec![];
+        let mut texture_bind_groups = vec![];
+        for texture in textures.iter().chain([&white]) {
+            let (gpu_texture, view) = upload_texture(rc, texture);
+            texture_bind_groups.push(rc.device.create_bind_group(&BindGroupDescriptor {
+                label: Some("ModelRenderer::texture_bind_group"),
+                layout: &texture_bind_group_layout,
+                entries: &[
+                    BindGroupEntry {
+                        binding: 0,
+                        resource: BindingResource::TextureView(&view),
+                    },
+                    BindGroupEntry {
+                        binding: 1,
+                        resource: BindingResource::Sampler(&sampler),
+                    },
+                ],
+            }));
+            gpu_textures.push(gpu_texture);
+        }
+
+        let model_textures = models
+            .iter()
+            .map(|model| model.texture.filter(|texture| *texture < textures.len()).unwrap_or(textures.len()))
+            .collect();
+
         Self {
             shader,
             bind_group_layout,
@@ -183,7 +317,13 @@ impl ModelRenderer {
             instances_len,
             instances,
             indices,
+            model_ranges,
             bind_group,
+            texture_bind_group_layout,
+            sampler,
+            textures: gpu_textures,
+            texture_bind_groups,
+            model_textures,
         }
     }
 
@@ -192,16 +332,38 @@ impl ModelRenderer {
         rc: &RenderingContext,
         surface_view: &TextureView,
         depth_stencil_view: &TextureView,
-        instances: &[Instance],
+        viewports: &[Viewport],
+        render_list: &RenderList,
     ) {
+        if render_list.is_empty() {
+            return;
+        }
+
+        // Lay the instances out model by model, with models sharing a texture next to each other
+        let mut models: Vec<(usize, &[Instance])> = render_list
+            .batches()
+            .map(|(model, instances)| (model as usize, instances))
+            .collect();
+        models.sort_by_key(|(model, _)| self.model_textures.get(*model).copied());
+
+        let mut instances = Vec::with_capacity(render_list.len());
+        let mut batches: Vec<(usize, Range<u32>)> = vec![];
+        for (model, model_instances) in models {
+            let start = instances.len() as u32;
+            instances.extend_from_slice(model_instances);
+            batches.push((model, start..instances.len() as u32));
+        }
+
         // Rebuild our instance buffer on size mismatch
         if self.instances_len < instances.len() {
-            self.instances_len = self.instances_len / 2 * 3;
+            while self.instances_len < instances.len() {
+                self.instances_len = self.instances_len / 2 * 3;
+            }
             self.instances = create_instance_buffer(rc, self.instances_len);
         }
 
         // Write to our instance buffer
-        rc.queue.write_buffer(&self.instances, 0, bytemuck::cast_slice(instances));
+        rc.queue.write_buffer(&self.instances, 0, bytemuck::cast_slice(&instances));
 
         // Build our command encoder
         let mut command_encoder = rc.device.create_command_encoder(&CommandEncoderDescriptor {
@@ -234,8 +396,26 @@ impl ModelRenderer {
             render_pass.set_vertex_buffer(0, self.vertices.slice(..));
             render_pass.set_vertex_buffer(1, self.instances.slice(..));
             render_pass.set_index_buffer(self.indices.slice(..), IndexFormat::Uint32);
-            render_pass.set_bind_group(0, &self.bind_group, &[]);
-            render_pass.draw_indexed(0..864, 0, 0..instances.len() as u32);
+            for viewport in viewports {
+                set_viewport(&mut render_pass, viewport);
+                render_pass.set_bind_group(0, &self.bind_group, &[viewport.globals_offset]);
+
+                let mut bound_texture = None;
+                for (model, batch) in &batches {
+                    let range = match self.model_ranges.get(*model) {
+                        Some(range) => range,
+                        None => continue,
+                    };
+
+                    let texture = self.model_textures[*model];
+                    if bound_texture != Some(texture) {
+                        render_pass.set_bind_group(1, &self.texture_bind_groups[texture], &[]);
+                        bound_texture = Some(texture);
+                    }
+
+                    render_pass.draw_indexed(range.indices.clone(), range.base_vertex, batch.clone());
+                }
+            }
         }
 
         // Submit our work
@@ -251,3 +431,43 @@ fn create_instance_buffer(rc: &RenderingContext, instances_len: usize) -> Buffer
         mapped_at_creation: false,
     })
 }
+
+// Helper function for uploading a texture and every one of its mip levels
+fn upload_texture(rc: &RenderingContext, texture: &super::Texture) -> (Texture, TextureView) {
+    let gpu_texture = rc.device.create_texture(&TextureDescriptor {
+        label: Some("ModelRenderer::texture"),
+        size: Extent3d {
+            width: texture.resolution.width,
+            height: texture.resolution.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: texture.mip_level_count(),
+        sample_count: 1,
+        dimension: TextureDimension::D2,
+        format: TextureFormat::Rgba8UnormSrgb,
+        usage: TextureUsages::COPY_DST | TextureUsages::TEXTURE_BINDING,
+    });
+
+    for (mip_level, mipmap) in texture.mipmaps().iter().enumerate() {
+        let (width, height) = mipmap.dimensions();
+        rc.queue.write_texture(
+            ImageCopyTexture {
+                texture: &gpu_texture,
+                mip_level: mip_level as u32,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            mipmap.as_raw(),
+            ImageDataLayout {
+                offset: 0,
+                bytes_per_row: NonZeroU32::new(4 * width),
+                rows_per_image: NonZeroU32::new(height),
+            },
+            Extent3d { width, height, depth_or_array_layers: 1 },
+        );
+    }
+
+    let view = gpu_texture.create_view(&TextureViewDescriptor::default());
+
+    (gpu_texture, view)
+}
@@ -0,0 +1,43 @@
+use std::collections::BTreeMap;
+
+use super::Instance;
+
+/// Everything to draw this frame, grouped by model index so each model is drawn in one call
+///
+/// Each model is drawn with a single texture, so grouping by model groups by material as well, and the renderer
+/// orders the batches by texture. Translucency is deliberately out of scope: models are alpha tested, each pixel
+/// either fully drawn or discarded, so nothing has to be sorted back to front and instances carry no sort key.
+#[derive(Clone, Debug, Default)]
+pub struct RenderList {
+    batches: BTreeMap<u32, Vec<Instance>>,
+}
+
+impl RenderList {
+    /// Empties the list for the next frame, keeping its allocations
+    pub fn clear(&mut self) {
+        for instances in self.batches.values_mut() {
+            instances.clear();
+        }
+    }
+
+    pub fn push(&mut self, model: u32, instance: Instance) {
+        self.batches.entry(model).or_default().push(instance);
+    }
+
+    /// The instances of every model with anything to draw, in model order
+    pub fn batches(&self) -> impl Iterator<Item = (u32, &[Instance])> {
+        self.batches
+            .iter()
+            .filter(|(_, instances)| !instances.is_empty())
+            .map(|(model, instances)| (*model, instances.as_slice()))
+    }
+
+    /// How many instances there are across every model
+    pub fn len(&self) -> usize {
+        self.batches.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.batches.values().all(Vec::is_empty)
+    }
+}
@@ -37,11 +37,11 @@ var<uniform> locals: Locals;
 fn vs_main(
     [[location(0)]] position: vec3<f32>,
 ) -> VertexOutput {
-    let position = vec4<f32>(position / 16.0, 1.0);
+    let position = Z_UP_TO_Y_UP * vec4<f32>(position / 16.0, 1.0);
 
     var out: VertexOutput;
     out.position = position.xyz;
-    out.normal = (vec4<f32>(locals.normal, 1.0)).xyz;
+    out.normal = (Z_UP_TO_Y_UP * vec4<f32>(locals.normal, 0.0)).xyz;
     out.clip_position = globals.view_proj * position;
     return out;
 }
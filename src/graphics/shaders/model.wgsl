@@ -9,12 +9,19 @@ struct Globals {
 struct VertexOutput {
     [[location(0)]] position: vec3<f32>;
     [[location(1)]] normal: vec3<f32>;
+    [[location(2)]] tex_coord: vec2<f32>;
     [[builtin(position)]] clip_position: vec4<f32>;
 };
 
 [[group(0), binding(0)]]
 var<uniform> globals: Globals;
 
+[[group(1), binding(0)]]
+var diffuse_texture: texture_2d<f32>;
+
+[[group(1), binding(1)]]
+var diffuse_sampler: sampler;
+
 [[stage(vertex)]]
 fn vs_main(
     [[location(0)]] position: vec3<f32>,
@@ -35,6 +42,7 @@ fn vs_main(
     var out: VertexOutput;
     out.position = position.xyz;
     out.normal = normal_matrix * normal;
+    out.tex_coord = tex_coord;
     out.clip_position = globals.view_proj * position;
     return out;
 }
@@ -43,6 +51,12 @@ fn vs_main(
 fn fs_main(in: VertexOutput) -> [[location(0)]] vec4<f32> {
     let light_position = vec3<f32>(0.0, 10.0, 0.0);
     let light_dir = normalize(light_position - in.position);
-    let light = vec3<f32>(max(dot(in.normal, light_dir), 0.0) + 0.05);
-    return vec4<f32>(light, 1.0);
+    let light = max(dot(in.normal, light_dir), 0.0) + 0.05;
+    let colour = textureSample(diffuse_texture, diffuse_sampler, in.tex_coord);
+
+    // Models are alpha tested rather than blended, so they can be drawn in any order
+    if (colour.a < 0.5) {
+        discard;
+    }
+    return vec4<f32>(colour.rgb * light, 1.0);
 }
@@ -2,10 +2,13 @@ use std::fmt::Debug;
 use std::path::Path;
 
 use image::GenericImageView;
+use image::RgbaImage;
+use image::imageops::FilterType;
 
 use crate::Result;
 use crate::components::Resolution;
 
+/// An image loaded from disk as RGBA8
 pub struct Texture {
     pub data: Vec<u8>,
     pub resolution: Resolution,
@@ -17,8 +20,39 @@ impl Texture {
         let resolution = image.dimensions().into();
 
         Ok(Self {
-            data: image.into_rgb8().into_vec(),
+            data: image.into_rgba8().into_vec(),
             resolution,
         })
     }
+
+    /// A single white pixel, for models without a texture
+    pub fn white() -> Self {
+        Self {
+            data: vec![255; 4],
+            resolution: Resolution { width: 1, height: 1 },
+        }
+    }
+
+    /// How many mip levels a full chain down to a single pixel has
+    pub fn mip_level_count(&self) -> u32 {
+        32 - self.resolution.width.max(self.resolution.height).max(1).leading_zeros()
+    }
+
+    /// Every mip level of the texture, starting with the full size image and halving down to a single pixel
+    pub fn mipmaps(&self) -> Vec<RgbaImage> {
+        let width = self.resolution.width;
+        let height = self.resolution.height;
+        let full = RgbaImage::from_raw(width, height, self.data.clone())
+            .expect("texture data should match its resolution");
+
+        let mut mipmaps = vec![full];
+        for level in 1..self.mip_level_count() {
+            let width = (width >> level).max(1);
+            let height = (height >> level).max(1);
+            let mipmap = image::imageops::resize(&mipmaps[0], width, height, FilterType::Triangle);
+            mipmaps.push(mipmap);
+        }
+
+        mipmaps
+    }
 }
@@ -0,0 +1,51 @@
+use crate::components::Resolution;
+
+/// A region of the surface drawn from one camera's point of view
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Viewport {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Where this viewport's globals live in the globals buffer
+    pub globals_offset: u32,
+}
+
+impl Viewport {
+    /// Splits the surface between `count` players: whole, halves stacked top and bottom, one half over two
+    /// quarters, or four quarters
+    pub fn layout(resolution: Resolution, count: usize, globals_stride: u32) -> Vec<Self> {
+        let width = resolution.width;
+        let height = resolution.height;
+        let half_width = width / 2;
+        let half_height = height / 2;
+
+        let regions: &[(u32, u32, u32, u32)] = match count {
+            0 | 1 => &[(0, 0, width, height)],
+            2 => &[(0, 0, width, half_height), (0, half_height, width, height - half_height)],
+            3 => &[
+                (0, 0, width, half_height),
+                (0, half_height, half_width, height - half_height),
+                (half_width, half_height, width - half_width, height - half_height),
+            ],
+            _ => &[
+                (0, 0, half_width, half_height),
+                (half_width, 0, width - half_width, half_height),
+                (0, half_height, half_width, height - half_height),
+                (half_width, half_height, width - half_width, height - half_height),
+            ],
+        };
+
+        regions
+            .iter()
+            .enumerate()
+            .map(|(i, &(x, y, width, height))| Self {
+                x,
+                y,
+                width: width.max(1),
+                height: height.max(1),
+                globals_offset: i as u32 * globals_stride,
+            })
+            .collect()
+    }
+}
@@ -1,97 +1,502 @@
+use std::collections::BTreeMap;
+use std::collections::HashSet;
 use std::f32::consts::PI;
+use std::time::Instant;
 
+use gilrs::Axis;
+use gilrs::Button;
+use gilrs::EventType;
+use gilrs::GamepadId;
 use gilrs::Gilrs;
 use nalgebra::Rotation2;
 use nalgebra::UnitQuaternion;
 use nalgebra::Vector2;
 use nalgebra::Vector3;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use tracing::info;
 use winit::event::ElementState;
 use winit::event::KeyboardInput;
+use winit::event::MouseButton;
 
 use crate::error::Error;
 
+const ESCAPE: u32 = 0x01;
 const W: u32 = 0x11;
+const E: u32 = 0x12;
+const R: u32 = 0x13;
 const A: u32 = 0x1E;
 const S: u32 = 0x1F;
 const D: u32 = 0x20;
+const SPACE: u32 = 0x39;
+const F6: u32 = 0x40;
+const F7: u32 = 0x41;
+const F8: u32 = 0x42;
+const F11: u32 = 0x57;
 
-const MAGIC_DELTA_MULTIPLIER: f32 = 0.005;
+/// The furthest up or down the view may be limited to in degrees, short of flipping over the top
+const MAX_PITCH_LIMIT: f32 = 89.0;
 
-#[derive(Clone, Copy, Debug, PartialEq)]
+/// The gentlest response curve a stick may have, anything flatter jumps straight to full deflection
+const MIN_RESPONSE_CURVE: f32 = 0.1;
+
+/// How many players can share a screen, any gamepads beyond this are left unassigned
+pub const MAX_LOCAL_PLAYERS: usize = 4;
+
+/// The input driving a single player for a frame
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
 pub struct InputState {
     pub move_direction: Vector2<f32>,
     pub view_direction: UnitQuaternion<f32>,
+    pub jump: bool,
+    pub fire: bool,
+    pub reload: bool,
+    /// Held while the use action is, for interacting with the world
+    pub interact: bool,
 }
 
-#[derive(Debug)]
-pub struct Input {
-    gilrs: Gilrs,
-    move_forward: u8,
-    move_backward: u8,
-    strafe_left: u8,
-    strafe_right: u8,
+impl Default for InputState {
+    fn default() -> Self {
+        Self {
+            move_direction: Vector2::zeros(),
+            view_direction: UnitQuaternion::identity(),
+            jump: false,
+            fire: false,
+            reload: false,
+            interact: false,
+        }
+    }
+}
+
+/// Something the player can do, which any number of bindings may trigger
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    MoveForward,
+    MoveBackward,
+    StrafeLeft,
+    StrafeRight,
+    Jump,
+    Fire,
+    Reload,
+    Use,
+    Pause,
+    /// Switches between windowed and borderless fullscreen
+    ToggleFullscreen,
+    /// Runs a single tick while paused, for developers
+    Step,
+    /// Halves the time scale, for developers
+    SlowDown,
+    /// Doubles the time scale, for developers
+    SpeedUp,
+}
+
+/// A physical button that can be bound to actions
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Binding {
+    /// A keyboard key by its scancode, so bindings stay in place across keyboard layouts
+    Key(u32),
+    Mouse(MouseButton),
+    Gamepad(Button),
+}
+
+/// The bindings of every action, an action may have any number of bindings
+///
+/// Actions missing from the settings file keep their default bindings, an empty list unbinds one
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Bindings(pub BTreeMap<Action, Vec<Binding>>);
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let bindings = [
+            (Action::MoveForward, vec![Binding::Key(W)]),
+            (Action::MoveBackward, vec![Binding::Key(S)]),
+            (Action::StrafeLeft, vec![Binding::Key(A)]),
+            (Action::StrafeRight, vec![Binding::Key(D)]),
+            (Action::Jump, vec![Binding::Key(SPACE), Binding::Gamepad(Button::South)]),
+            (Action::Fire, vec![Binding::Mouse(MouseButton::Left), Binding::Gamepad(Button::RightTrigger2)]),
+            (Action::Reload, vec![Binding::Key(R), Binding::Gamepad(Button::West)]),
+            (Action::Use, vec![Binding::Key(E), Binding::Gamepad(Button::East)]),
+            (Action::Pause, vec![Binding::Key(ESCAPE), Binding::Gamepad(Button::Start)]),
+            (Action::ToggleFullscreen, vec![Binding::Key(F11)]),
+            (Action::Step, vec![Binding::Key(F6)]),
+            (Action::SlowDown, vec![Binding::Key(F7)]),
+            (Action::SpeedUp, vec![Binding::Key(F8)]),
+        ];
+
+        Self(bindings.into_iter().collect())
+    }
+}
+
+impl<'de> Deserialize<'de> for Bindings {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut bindings = Self::default();
+        bindings.0.extend(BTreeMap::<Action, Vec<Binding>>::deserialize(deserializer)?);
+        Ok(bindings)
+    }
+}
+
+impl Bindings {
+    /// Every action `binding` is bound to
+    pub fn actions(&self, binding: Binding) -> impl Iterator<Item = Action> + '_ {
+        self.0
+            .iter()
+            .filter(move |(_, bindings)| bindings.contains(&binding))
+            .map(|(action, _)| *action)
+    }
+}
+
+/// How the mouse feels, deltas come straight from the device without any acceleration by the OS
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct MouseSettings {
+    /// Radians the view turns per count of mouse movement
+    pub sensitivity: f32,
+    pub invert_y: bool,
+    /// How far up or down the view can look in degrees, for gamepads as well
+    pub pitch_limit: f32,
+    /// The fraction of the last frame's movement blended into this one's, from 0 for none to just under 1
+    pub smoothing: f32,
+    /// Extra sensitivity per thousand counts per second of mouse speed, 0 turns acceleration off
+    pub acceleration: f32,
+}
+
+impl Default for MouseSettings {
+    fn default() -> Self {
+        Self {
+            sensitivity: 0.005,
+            invert_y: false,
+            pitch_limit: 72.0,
+            smoothing: 0.0,
+            acceleration: 0.0,
+        }
+    }
+}
+
+impl MouseSettings {
+    /// Keeps the pitch limit somewhere the view can't flip over, and smoothing short of freezing the view
+    pub fn clamped(self) -> Self {
+        Self {
+            pitch_limit: self.pitch_limit.clamp(0.0, MAX_PITCH_LIMIT),
+            smoothing: self.smoothing.clamp(0.0, 0.99),
+            acceleration: self.acceleration.max(0.0),
+            ..self
+        }
+    }
+}
+
+/// How gamepad sticks feel, shared by every gamepad
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct GamepadSettings {
+    /// How far a stick has to move from the centre before it does anything, from 0 to 1
+    pub deadzone: f32,
+    /// The power stick positions are raised to, higher values give finer control near the centre
+    pub response_curve: f32,
+    /// Radians per second the view turns with the right stick fully pushed over
+    pub look_sensitivity: f32,
+    pub invert_look: bool,
+}
+
+impl Default for GamepadSettings {
+    fn default() -> Self {
+        Self {
+            deadzone: 0.15,
+            response_curve: 2.0,
+            look_sensitivity: 3.0,
+            invert_look: false,
+        }
+    }
+}
+
+impl GamepadSettings {
+    /// Keeps the deadzone within the stick's travel and the response curve above zero
+    pub fn clamped(self) -> Self {
+        Self {
+            deadzone: self.deadzone.clamp(0.0, 1.0),
+            response_curve: self.response_curve.max(MIN_RESPONSE_CURVE),
+            ..self
+        }
+    }
+
+    /// Turns a raw stick position into how hard the player means to push it
+    fn shape(&self, stick: Vector2<f32>) -> Vector2<f32> {
+        let magnitude = stick.norm().min(1.0);
+        if magnitude <= self.deadzone || magnitude <= f32::EPSILON {
+            return Vector2::zeros();
+        }
+
+        // Rescale past the deadzone so the stick still reaches the full range
+        let scaled = ((magnitude - self.deadzone) / (1.0 - self.deadzone)).powf(self.response_curve);
+        stick.scale(scaled / magnitude)
+    }
+}
+
+/// The input of a single local player
+#[derive(Debug, Default)]
+struct LocalInput {
+    /// Every bound or unbound button currently held down
+    held: HashSet<Binding>,
     move_analog: Vector2<f32>,
     view_pitch: f32,
     view_yaw: f32,
 }
 
+impl LocalInput {
+    fn look(&mut self, yaw: f32, pitch: f32, pitch_limit: f32) {
+        self.view_pitch += pitch;
+        self.view_pitch = self.view_pitch.clamp(-pitch_limit, pitch_limit);
+        self.view_yaw += yaw;
+        self.view_yaw %= 2.0 * PI;
+    }
+}
+
+/// Gathers the input of every local player
+///
+/// The first player plays on keyboard and mouse as well as the first gamepad,
+/// and every gamepad after that brings another player
+#[derive(Debug)]
+pub struct Input {
+    gilrs: Gilrs,
+    bindings: Bindings,
+    mouse_settings: MouseSettings,
+    gamepad_settings: GamepadSettings,
+    players: Vec<LocalInput>,
+    /// Mouse movement since the last update
+    mouse_delta: Vector2<f32>,
+    /// The mouse movement applied on the last update, for smoothing
+    smoothed_mouse_delta: Vector2<f32>,
+    /// The gamepad driving each player, kept free while a gamepad is unplugged so it can rejoin
+    gamepads: Vec<Option<GamepadId>>,
+    /// Actions whose bindings were pressed since they were last taken, by any player
+    pressed: Vec<Action>,
+    last_update: Instant,
+}
+
 impl Input {
-    pub fn new() -> Result<Self, Error> {
+    pub fn new(
+        bindings: Bindings,
+        mouse_settings: MouseSettings,
+        gamepad_settings: GamepadSettings,
+    ) -> Result<Self, Error> {
         let gilrs = Gilrs::new()?;
 
-        Ok(Self {
+        let mut input = Self {
             gilrs,
-            move_forward: 0,
-            move_backward: 0,
-            strafe_left: 0,
-            strafe_right: 0,
-            move_analog: Vector2::zeros(),
-            view_pitch: 0.0,
-            view_yaw: 0.0,
-        })
+            bindings,
+            mouse_settings,
+            gamepad_settings,
+            players: vec![LocalInput::default()],
+            mouse_delta: Vector2::zeros(),
+            smoothed_mouse_delta: Vector2::zeros(),
+            gamepads: vec![],
+            pressed: vec![],
+            last_update: Instant::now(),
+        };
+
+        let connected: Vec<GamepadId> = input.gilrs.gamepads().map(|(id, _)| id).collect();
+        for id in connected {
+            input.assign_gamepad(id);
+        }
+
+        Ok(input)
+    }
+
+    /// Swaps in a new set of bindings, taking effect immediately
+    pub fn set_bindings(&mut self, bindings: Bindings) {
+        self.bindings = bindings;
+    }
+
+    pub fn set_mouse_settings(&mut self, mouse_settings: MouseSettings) {
+        self.mouse_settings = mouse_settings;
+    }
+
+    pub fn set_gamepad_settings(&mut self, gamepad_settings: GamepadSettings) {
+        self.gamepad_settings = gamepad_settings;
+    }
+
+    /// The number of local players, which only grows as gamepads are connected
+    pub fn player_count(&self) -> usize {
+        self.players.len()
     }
 
     pub fn update_key_state(&mut self, event: KeyboardInput) {
-        let state = match event.state {
-            ElementState::Pressed => 1,
-            ElementState::Released => 0,
-        };
+        self.update_binding_state(0, Binding::Key(event.scancode), event.state);
+    }
 
-        match event.scancode {
-            i if i == W => self.move_forward = state,
-            i if i == S => self.move_backward = state,
-            i if i == A => self.strafe_left = state,
-            i if i == D => self.strafe_right = state,
-            _ => (),
-        }
+    pub fn update_mouse_button_state(&mut self, button: MouseButton, state: ElementState) {
+        self.update_binding_state(0, Binding::Mouse(button), state);
     }
 
     pub fn apply_mouse_delta(&mut self, delta: (f64, f64)) {
-        self.view_pitch += delta.1 as f32 * MAGIC_DELTA_MULTIPLIER;
-        self.view_pitch = self.view_pitch.clamp(-PI * 0.4, PI * 0.4);
-        self.view_yaw += delta.0 as f32 * MAGIC_DELTA_MULTIPLIER;
-        self.view_yaw %= 2.0 * PI;
+        self.mouse_delta += Vector2::new(delta.0 as f32, delta.1 as f32);
     }
 
-    pub fn get_state(&mut self) -> InputState {
-        while let Some(_event) = self.gilrs.next_event() {
-            // TODO: handle the event
+    /// Takes every action pressed since the last call, for actions that happen once per press
+    pub fn take_pressed_actions(&mut self) -> Vec<Action> {
+        std::mem::take(&mut self.pressed)
+    }
+
+    /// Turns the view by the mouse and sticks and handles gamepad events, should be called once per frame
+    pub fn update(&mut self) {
+        let delta_time = self.last_update.elapsed().as_secs_f32();
+        self.last_update = Instant::now();
+
+        let pitch_limit = self.mouse_settings.pitch_limit.to_radians();
+        self.update_mouse_look(delta_time, pitch_limit);
+
+        while let Some(event) = self.gilrs.next_event() {
+            match event.event {
+                EventType::Connected => {
+                    info!("Gamepad connected");
+                    self.assign_gamepad(event.id);
+                },
+                EventType::Disconnected => {
+                    info!("Gamepad disconnected");
+                    self.unassign_gamepad(event.id);
+                },
+                EventType::ButtonPressed(button, _) => {
+                    if let Some(player) = self.gamepad_player(event.id) {
+                        self.update_binding_state(player, Binding::Gamepad(button), ElementState::Pressed);
+                    }
+                },
+                EventType::ButtonReleased(button, _) => {
+                    if let Some(player) = self.gamepad_player(event.id) {
+                        self.update_binding_state(player, Binding::Gamepad(button), ElementState::Released);
+                    }
+                },
+                _ => (),
+            }
         }
 
-        let mut move_direction = self.move_analog;
-        move_direction.y += self.move_forward as u32 as f32;
-        move_direction.y -= self.move_backward as u32 as f32;
-        move_direction.x -= self.strafe_left as u32 as f32;
-        move_direction.x += self.strafe_right as u32 as f32;
-        move_direction = Rotation2::new(-self.view_yaw) * move_direction;
+        for (player, id) in self.gamepads.iter().enumerate() {
+            let gamepad = match id.and_then(|id| self.gilrs.connected_gamepad(id)) {
+                Some(gamepad) => gamepad,
+                None => continue,
+            };
+
+            let settings = &self.gamepad_settings;
+            let stick = |x, y| settings.shape(Vector2::new(gamepad.value(x), gamepad.value(y)));
+            let left = stick(Axis::LeftStickX, Axis::LeftStickY);
+            let right = stick(Axis::RightStickX, Axis::RightStickY);
+
+            // Pushing the stick up looks up, which is the opposite way to the mouse
+            let pitch_direction = if settings.invert_look { 1.0 } else { -1.0 };
+            let turn = settings.look_sensitivity * delta_time;
+
+            let player = &mut self.players[player];
+            player.move_analog = left;
+            player.look(right.x * turn, right.y * turn * pitch_direction, pitch_limit);
+        }
+    }
+
+    pub fn get_state(&self, player: usize) -> InputState {
+        let local = &self.players[player];
+
+        let mut move_direction = local.move_analog;
+        move_direction.y += self.axis(player, Action::MoveBackward, Action::MoveForward);
+        move_direction.x += self.axis(player, Action::StrafeLeft, Action::StrafeRight);
+        move_direction = Rotation2::new(-local.view_yaw) * move_direction;
         move_direction = move_direction.cap_magnitude(1.0);
         
-        let mut view_direction = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), self.view_yaw);
-        view_direction = view_direction * UnitQuaternion::from_axis_angle(&Vector3::x_axis(), self.view_pitch);
+        let mut view_direction = UnitQuaternion::from_axis_angle(&Vector3::y_axis(), local.view_yaw);
+        view_direction = view_direction * UnitQuaternion::from_axis_angle(&Vector3::x_axis(), local.view_pitch);
 
         InputState {
             move_direction,
             view_direction,
+            jump: self.is_held(player, Action::Jump),
+            fire: self.is_held(player, Action::Fire),
+            reload: self.is_held(player, Action::Reload),
+            interact: self.is_held(player, Action::Use),
         }
     }
+
+    // Helper function for turning player 0's view by the mouse movement since the last update
+    fn update_mouse_look(&mut self, delta_time: f32, pitch_limit: f32) {
+        let settings = self.mouse_settings;
+        let delta = std::mem::replace(&mut self.mouse_delta, Vector2::zeros());
+
+        let smoothing = settings.smoothing.clamp(0.0, 0.99);
+        let smoothed = self.smoothed_mouse_delta.scale(smoothing) + delta.scale(1.0 - smoothing);
+        self.smoothed_mouse_delta = smoothed;
+
+        // Faster movements turn further per count when accelerated
+        let speed = match delta_time > f32::EPSILON {
+            true => smoothed.norm() / delta_time,
+            false => 0.0,
+        };
+        let gain = settings.sensitivity * (1.0 + settings.acceleration * speed / 1000.0);
+
+        let pitch_direction = if settings.invert_y { -1.0 } else { 1.0 };
+        self.players[0].look(smoothed.x * gain, smoothed.y * gain * pitch_direction, pitch_limit);
+    }
+
+    fn assign_gamepad(&mut self, id: GamepadId) {
+        if self.gamepad_player(id).is_some() {
+            return;
+        }
+
+        // Fill the slot of a gamepad that was unplugged before bringing in a new player
+        match self.gamepads.iter().position(Option::is_none) {
+            Some(player) => self.gamepads[player] = Some(id),
+            None if self.gamepads.len() < MAX_LOCAL_PLAYERS => self.gamepads.push(Some(id)),
+            None => {
+                info!("Gamepad ignored, there are already {MAX_LOCAL_PLAYERS} players");
+                return;
+            },
+        }
+
+        while self.players.len() < self.gamepads.len() {
+            self.players.push(LocalInput::default());
+        }
+    }
+
+    fn unassign_gamepad(&mut self, id: GamepadId) {
+        let player = match self.gamepad_player(id) {
+            Some(player) => player,
+            None => return,
+        };
+
+        self.gamepads[player] = None;
+
+        // Let go of everything the gamepad was holding
+        let local = &mut self.players[player];
+        local.held.retain(|binding| !matches!(binding, Binding::Gamepad(_)));
+        local.move_analog = Vector2::zeros();
+    }
+
+    fn gamepad_player(&self, id: GamepadId) -> Option<usize> {
+        self.gamepads.iter().position(|gamepad| *gamepad == Some(id))
+    }
+
+    fn update_binding_state(&mut self, player: usize, binding: Binding, state: ElementState) {
+        let held = &mut self.players[player].held;
+        match state {
+            ElementState::Pressed => {
+                // Holding a key down repeats its press, which shouldn't trigger its actions again
+                if held.insert(binding) {
+                    self.pressed.extend(self.bindings.actions(binding));
+                }
+            },
+            ElementState::Released => {
+                held.remove(&binding);
+            },
+        }
+    }
+
+    fn is_held(&self, player: usize, action: Action) -> bool {
+        let held = &self.players[player].held;
+        self.bindings.0
+            .get(&action)
+            .map_or(false, |bindings| bindings.iter().any(|binding| held.contains(binding)))
+    }
+
+    // Helper function for turning a pair of opposing actions into a value from -1 to 1
+    fn axis(&self, player: usize, negative: Action, positive: Action) -> f32 {
+        self.is_held(player, positive) as u32 as f32 - self.is_held(player, negative) as u32 as f32
+    }
 }
@@ -0,0 +1,151 @@
+use std::collections::VecDeque;
+
+use nalgebra::Point3;
+use nalgebra::UnitQuaternion;
+
+/// The longest we keep moving an entity past its newest snapshot before holding it in place
+const MAX_EXTRAPOLATION: f32 = 0.25;
+/// The most snapshots we keep for a single entity
+const MAX_SAMPLES: usize = 32;
+
+/// The server time remote entities are currently shown at, which trails the server by the render delay
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RenderTime(pub f32);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Sample {
+    time: f32,
+    position: Point3<f32>,
+    rotation: UnitQuaternion<f32>,
+}
+
+/// The recent snapshots of a networked entity, oldest first, for showing it smoothly between updates
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SnapshotBuffer {
+    samples: VecDeque<Sample>,
+}
+
+impl SnapshotBuffer {
+    /// Adds the entity's state at the given server time, which must be newer than any before it
+    pub fn push(&mut self, time: f32, position: Point3<f32>, rotation: UnitQuaternion<f32>) {
+        self.samples.push_back(Sample { time, position, rotation });
+        if self.samples.len() > MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.samples.clear();
+    }
+
+    /// Works out where the entity was at the given server time
+    ///
+    /// Past the newest snapshot the entity carries on the way it was heading for a little while
+    pub fn sample(&mut self, time: f32) -> Option<(Point3<f32>, UnitQuaternion<f32>)> {
+        // Drop the snapshots we've moved past, keeping two around to extrapolate from
+        while self.samples.len() > 2 && self.samples[1].time <= time {
+            self.samples.pop_front();
+        }
+
+        let from = *self.samples.front()?;
+        let to = match self.samples.get(1) {
+            Some(to) => *to,
+            None => return Some((from.position, from.rotation)),
+        };
+
+        let span = to.time - from.time;
+        if span <= f32::EPSILON {
+            return Some((to.position, to.rotation));
+        }
+
+        let t = ((time - from.time) / span).clamp(0.0, 1.0 + MAX_EXTRAPOLATION / span);
+        let position = from.position + (to.position - from.position).scale(t);
+        let rotation = from.rotation
+            .try_slerp(&to.rotation, t.min(1.0), f32::EPSILON)
+            .unwrap_or(to.rotation);
+
+        Some((position, rotation))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::FRAC_PI_2;
+    use std::f32::consts::FRAC_PI_4;
+
+    use nalgebra::Point3;
+    use nalgebra::UnitQuaternion;
+    use nalgebra::Vector3;
+    use nalgebra::point;
+
+    use super::MAX_EXTRAPOLATION;
+    use super::SnapshotBuffer;
+
+    const EPSILON: f32 = 1e-4;
+
+    // A buffer of samples moving along the x axis, each given as its time and x
+    fn along_x(samples: &[(f32, f32)]) -> SnapshotBuffer {
+        let mut buffer = SnapshotBuffer::default();
+        for &(time, x) in samples {
+            buffer.push(time, point![x, 0.0, 0.0], UnitQuaternion::identity());
+        }
+        buffer
+    }
+
+    fn x_at(buffer: &mut SnapshotBuffer, time: f32) -> f32 {
+        let (position, _) = buffer.sample(time).unwrap();
+        position.x
+    }
+
+    #[test]
+    fn nothing_to_sample_without_snapshots() {
+        assert!(SnapshotBuffer::default().sample(0.0).is_none());
+    }
+
+    #[test]
+    fn single_sample_holds_still() {
+        let mut buffer = along_x(&[(1.0, 2.0)]);
+        assert_eq!(buffer.sample(0.0).unwrap().0, point![2.0, 0.0, 0.0]);
+        assert_eq!(buffer.sample(5.0).unwrap().0, point![2.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn interpolates_between_samples() {
+        let mut buffer = SnapshotBuffer::default();
+        buffer.push(0.0, Point3::origin(), UnitQuaternion::identity());
+        buffer.push(1.0, point![10.0, 0.0, 0.0], UnitQuaternion::from_axis_angle(&Vector3::y_axis(), FRAC_PI_2));
+
+        let (position, rotation) = buffer.sample(0.5).unwrap();
+        assert!((position.x - 5.0).abs() < EPSILON);
+        assert!((rotation.angle() - FRAC_PI_4).abs() < EPSILON);
+    }
+
+    #[test]
+    fn holds_at_the_oldest_sample_before_it() {
+        let mut buffer = along_x(&[(1.0, 0.0), (2.0, 10.0)]);
+        assert!(x_at(&mut buffer, 0.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn moves_on_past_samples_it_has_passed() {
+        let mut buffer = along_x(&[(0.0, 0.0), (1.0, 10.0), (2.0, 30.0)]);
+        assert!((x_at(&mut buffer, 1.5) - 20.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn extrapolation_is_capped() {
+        // Moving at 100 units a second, so it should stop MAX_EXTRAPOLATION seconds past the newest sample
+        let mut buffer = along_x(&[(0.0, 0.0), (0.1, 10.0)]);
+        let halfway = 10.0 + 100.0 * MAX_EXTRAPOLATION / 2.0;
+        let limit = 10.0 + 100.0 * MAX_EXTRAPOLATION;
+
+        assert!((x_at(&mut buffer, 0.1 + MAX_EXTRAPOLATION / 2.0) - halfway).abs() < EPSILON);
+        assert!((x_at(&mut buffer, 10.0) - limit).abs() < EPSILON);
+    }
+
+    #[test]
+    fn zero_span_snaps_to_the_newest_sample() {
+        let mut buffer = along_x(&[(1.0, 0.0), (1.0, 5.0)]);
+        assert!((x_at(&mut buffer, 1.0) - 5.0).abs() < EPSILON);
+    }
+}
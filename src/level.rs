@@ -0,0 +1,84 @@
+use legion::Entity;
+use legion::IntoQuery;
+use legion::Resources;
+use legion::World;
+use legion::component;
+use mappy::Map;
+use nalgebra::Point3;
+use nalgebra::Vector3;
+use tracing::warn;
+
+use crate::addon::Content;
+use crate::components::Dead;
+use crate::components::Health;
+use crate::components::PlayerBrain;
+use crate::components::Position;
+use crate::components::PreviousPosition;
+use crate::components::PreviousRotation;
+use crate::components::Velocity;
+use crate::error::Error;
+use crate::physics::MapCollider;
+use crate::spawner;
+use crate::waves::WaveController;
+
+/// The internal name of a map to switch to once the frame is done, set when a player enters a warp zone
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct PendingWarp(pub Option<String>);
+
+/// Reads the source of the named map from the loaded content
+pub fn read_map(content: &Content, name: &str) -> Result<String, Error> {
+    let path = content.maps.get(name).ok_or_else(|| Error::MapNotFound(name.to_string()))?;
+    Ok(std::fs::read_to_string(path)?)
+}
+
+/// Tears down everything but the players and fills the world from `map`
+///
+/// Players are moved to the new map's player spawn and brought back to full health. Fails without touching
+/// the world if the map has nothing to collide with
+pub fn change_map(world: &mut World, resources: &mut Resources, map: &Map<'_>) -> Result<(), Error> {
+    let map_collider = MapCollider::from_map(map)?;
+
+    let doomed: Vec<Entity> = <Entity>::query()
+        .filter(!component::<PlayerBrain>())
+        .iter(world)
+        .copied()
+        .collect();
+
+    for entity in doomed {
+        world.remove(entity);
+    }
+
+    spawner::spawn_map_entities(world, map);
+
+    let spawn_position = spawner::find_player_spawn(world).unwrap_or_else(|| {
+        warn!("No player_spawn in map, spawning at the origin");
+        Point3::origin()
+    });
+
+    let players: Vec<Entity> = <Entity>::query()
+        .filter(component::<PlayerBrain>())
+        .iter(world)
+        .copied()
+        .collect();
+
+    for player in players {
+        if let Some(mut entry) = world.entry(player) {
+            entry.add_component(Position(spawn_position));
+            entry.add_component(Velocity(Vector3::zeros()));
+            entry.add_component(Health(spawner::PLAYER_HEALTH));
+            entry.remove_component::<Dead>();
+
+            // Otherwise the next frame would be drawn partway between the old map and the new one
+            entry.remove_component::<PreviousPosition>();
+            entry.remove_component::<PreviousRotation>();
+        }
+    }
+
+    resources.insert(map_collider);
+
+    if let Some(mut waves) = resources.get_mut::<WaveController>() {
+        *waves = WaveController::new(waves.zombie_model);
+    }
+
+    Ok(())
+}
@@ -2,23 +2,38 @@ mod addon;
 mod camera;
 mod components;
 mod error;
+mod events;
 mod graphics;
 mod input;
+mod interpolation;
+mod level;
+mod movement;
+mod net;
+mod physics;
+mod replay;
+mod score;
+mod settings;
+mod spawner;
 mod time;
 mod systems;
+mod waves;
+mod weapon;
 
 use std::collections::HashMap;
 use std::net::Ipv4Addr;
+use std::path::PathBuf;
+use std::time::Duration;
 
 use clap::Parser;
+use legion::Entity;
 use legion::Resources;
 use legion::Schedule;
 use legion::World;
+use legion::systems::Builder;
 use mappy::Map;
 use nalgebra::Point3;
-use nalgebra::UnitQuaternion;
-use nalgebra::Vector3;
-use tokio::sync::mpsc;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
 use tracing::error;
 use tracing::info;
 use tracing::warn;
@@ -28,24 +43,63 @@ use winit::event::Event;
 use winit::event::WindowEvent;
 use winit::event_loop::ControlFlow;
 use winit::event_loop::EventLoop;
+use winit::monitor::MonitorHandle;
+use winit::monitor::VideoMode;
+use winit::window::Fullscreen;
+use winit::window::Window;
 use winit::window::WindowBuilder;
 
 use self::addon::Addon;
+use self::addon::Content;
+use self::addon::Manifest;
 use self::camera::Camera;
-use self::components::PlayerBrain;
-use self::components::Position;
-use self::components::Resolution;
-use self::components::Rotation;
-use self::components::Speed;
-use self::components::Velocity;
+use self::camera::Cameras;
+use self::components::LocalPlayer;
+use self::components::Weapon;
 use self::error::Error;
+use self::events::DamageEvent;
+use self::events::DeathEvent;
+use self::events::Events;
 use self::graphics::Graphics;
+use self::graphics::RenderList;
+use self::input::Action;
 use self::input::Input;
+use self::input::InputState;
+use self::interpolation::RenderTime;
+use self::level::PendingWarp;
+use self::net::Client;
+use self::net::LinkConditions;
+use self::net::Network;
+use self::net::Server;
+use self::physics::MapCollider;
+use self::replay::Playback;
+use self::replay::Replay;
+use self::score::Score;
+use self::settings::SettingsFile;
+use self::settings::VideoSettings;
+use self::settings::WindowMode;
+use self::spawner::Loadout;
+use self::systems::apply_damage_system;
+use self::systems::apply_gravity_system;
+use self::systems::fire_weapons_system;
+use self::systems::interpolate_snapshots_system;
 use self::systems::render_models_system;
+use self::systems::spawn_zombies_system;
+use self::systems::store_previous_transforms_system;
+use self::systems::update_colliding_positions_system;
 use self::systems::update_player_camera_system;
 use self::systems::update_positions_system;
+use self::systems::update_player_rotations_system;
 use self::systems::update_player_velocities_system;
+use self::systems::update_projectiles_system;
+use self::systems::update_score_system;
+use self::systems::update_warp_zones_system;
+use self::systems::update_zombie_velocities_system;
+use self::time::FixedTimestep;
 use self::time::Time;
+use self::time::TimeControl;
+use self::waves::WaveController;
+use self::weapon::WeaponKind;
 
 const GAME_NAME: &str = env!("CARGO_PKG_NAME");
 const GAME_NAME_DISPLAY: &str = "Gungame";
@@ -57,6 +111,36 @@ struct Args {
     addon: Option<String>,
     /// Address of the server host (if any)
     host: Option<Ipv4Addr>,
+    /// Host a game that others can join
+    #[clap(long)]
+    server: bool,
+    /// Host a game without a window, for running on machines without a display
+    #[clap(long)]
+    dedicated: bool,
+    /// Simulation ticks per second, independent of the frame rate
+    #[clap(long, default_value_t = 60, parse(try_from_str = parse_tick_rate))]
+    tick_rate: u32,
+    /// Port to host on or connect to
+    #[clap(long, default_value_t = net::DEFAULT_PORT)]
+    port: u16,
+    /// Milliseconds behind the server that other players are shown, smoothing over late packets
+    #[clap(long, default_value_t = 100)]
+    render_delay: u64,
+    /// Milliseconds of latency to add to every packet we send, for testing online play
+    #[clap(long, default_value_t = 0)]
+    sim_latency: u64,
+    /// Fraction of the packets we send to drop, for testing online play
+    #[clap(long, default_value_t = 0.0)]
+    sim_loss: f32,
+    /// Record an offline session to the replays directory when the game is closed
+    #[clap(long)]
+    record: bool,
+    /// Play back a replay, either a path or the name of a file in the replays directory
+    #[clap(long)]
+    replay: Option<PathBuf>,
+    /// Override a setting for this run without saving it, for example `--set video.fov=100`
+    #[clap(long = "set", value_name = "KEY=VALUE")]
+    overrides: Vec<String>,
 }
 
 type Result<T> = std::result::Result<T, Error>;
@@ -72,75 +156,183 @@ async fn main() -> Result<()> {
     let document_dir = userdirs.document_dir().ok_or(Error::NoDocumentDirectory)?;
     let game_dir = document_dir.join(GAME_NAME_DISPLAY);
     let addons_dir = game_dir.join("addons");
+    let replays_dir = game_dir.join("replays");
 
-    // Load the given addon or base game otherwise
-    let addon_name = args.addon.unwrap_or(GAME_NAME.to_string());
-    let addon_dir = addons_dir.join(&addon_name);
-    let addon = Addon::from_path(&addon_dir.join(format!("{addon_name}.json")))?;
+    let mut settings_file = SettingsFile::new(game_dir.join("settings.json"), args.overrides);
+    let settings = settings_file.load()?;
+    let mut video = settings.video;
+
+    // Replays bring their own addon, map, seed and tick rate
+    let replay = match &args.replay {
+        Some(path) if path.is_file() => Some(Replay::from_path(path)?),
+        Some(path) => Some(Replay::from_path(replays_dir.join(path))?),
+        None => None,
+    };
+    let tick_rate = replay.as_ref().map_or(args.tick_rate, |replay| replay.tick_rate);
+    let seed = replay.as_ref().map_or_else(rand::random, |replay| replay.seed);
+
+    // Load the given addon or base game otherwise, along with its dependencies
+    let addon_name = match &replay {
+        Some(replay) => replay.addon.clone(),
+        None => args.addon.unwrap_or(GAME_NAME.to_string()),
+    };
+    let addons = Addon::load_with_dependencies(&addons_dir, &addon_name)?;
+    let content = Content::from_addons(&addons);
+    let manifest = Manifest::new(&addons, &content)?;
+
+    // Replays only play back the same on exactly the content they were recorded with
+    if let Some(replay) = &replay {
+        let mismatches = manifest.mismatches(&replay.manifest);
+        if !mismatches.is_empty() {
+            return Err(Error::ContentMismatch(mismatches));
+        }
+    }
 
     // Load our map
-    let map_path = match addon.maps.first() {
-        Some(map) => addon_dir.join(map.1),
+    let start_map = match &replay {
+        Some(replay) => Some(replay.map.clone()),
+        None => content.start_map.clone(),
+    };
+    let map_data = match &start_map {
+        Some(map) => level::read_map(&content, map)?,
         None => {
             warn!("No maps to load, exiting...");
             return Ok(());
         },
     };
-    let map_data = std::fs::read_to_string(map_path)?;
     let map = Map::from_str(&map_data)?;
 
-    // Load models
-    let mut model_indices = HashMap::new();
-    let mut models = vec![];
-    for (i, (name, path)) in addon.models.iter().enumerate() {
-        model_indices.insert(name, i);
-        models.push(graphics::Model::from_obj(&addon_dir.join(path))?);
+    // Models are referred to by their index in the content
+    let model_indices: HashMap<&str, u32> = content.models
+        .keys()
+        .enumerate()
+        .map(|(i, name)| (name.as_str(), i as u32))
+        .collect();
+    let zombie_model = model_indices.get("zombie").copied().map(components::Model);
+    if zombie_model.is_none() {
+        warn!("No `zombie` model in the loaded addons, zombies will be invisible");
+    }
+
+    // Work out what every player spawns with
+    let starting_weapon = content.starting_weapon.as_ref().and_then(|name| content.weapons.get(name));
+    let loadout = Loadout {
+        model: model_indices.get("player").copied().map(components::Model),
+        weapon: starting_weapon.map(|stats| {
+            let projectile_model = match &stats.kind {
+                WeaponKind::Projectile { model: Some(model), .. } => {
+                    model_indices.get(model.as_str()).copied().map(components::Model)
+                },
+                _ => None,
+            };
+
+            Weapon { stats: stats.clone(), projectile_model, cooldown: 0.0, ammo: stats.magazine.unwrap_or(0) }
+        }),
+    };
+
+    let mut current_map = start_map.unwrap_or_default();
+    let conditions = LinkConditions {
+        latency: Duration::from_millis(args.sim_latency),
+        loss: args.sim_loss,
+        seed: None,
+    };
+
+    // Dedicated servers never open a window, so they run their own loop
+    if args.dedicated {
+        let server = Server::bind(args.port, &current_map, manifest, conditions)?;
+        return run_dedicated(server, &content, &map, &loadout, zombie_model, tick_rate, seed).await;
     }
 
     // Load textures
     let mut texture_indices = HashMap::new();
     let mut textures = vec![];
-    for (i, (name, path)) in addon.textures.iter().enumerate() {
-        texture_indices.insert(name, i);
-        textures.push(graphics::Texture::from_file(&addon_dir.join(path))?);
+    for (i, (name, path)) in content.textures.iter().enumerate() {
+        texture_indices.insert(name.as_str(), i);
+        textures.push(graphics::Texture::from_file(path)?);
+    }
+
+    // Load models, in the same order as their indices, along with the textures they're drawn with
+    let mut models = vec![];
+    for (name, path) in &content.models {
+        let mut model = graphics::Model::from_obj(path)?;
+        if let Some(texture) = content.model_textures.get(name) {
+            model.texture = texture_indices.get(texture.as_str()).copied();
+            if model.texture.is_none() {
+                warn!("Model `{name}` uses the texture `{texture}`, which doesn't exist");
+            }
+        }
+        models.push(model);
     }
 
     // Set up our event loop
     let event_loop = EventLoop::new();
-    let mut resolution = Resolution { width: 1280, height: 720 };
+    let mut resolution = video.resolution();
     let window = WindowBuilder::new()
+        .with_title(GAME_NAME_DISPLAY)
         .with_inner_size::<PhysicalSize<u32>>(resolution.into())
+        .with_fullscreen(fullscreen(&video, event_loop.available_monitors(), event_loop.primary_monitor()))
         .build(&event_loop)?;
+    resolution = window.inner_size().into();
     let mut scale_factor = window.scale_factor();
+    let mut focused = true;
+    grab_cursor(&window, focused);
 
-    let (instance_sender, instance_receiver) = mpsc::unbounded_channel();
-
-    let mut graphics = Graphics::new(&window, resolution, &map, &models, instance_receiver).await?;
-    let mut input = Input::new()?;
+    let present_mode = video.present_mode.into();
+    let mut graphics = Graphics::new(&window, resolution, present_mode, &map, &models, &textures).await?;
+    let mut input = Input::new(settings.bindings, settings.mouse, settings.gamepad)?;
     let mut time = Time::new();
 
+    // Join or host a game if we've been asked to
+    let mut network = match (args.host, args.server) {
+        _ if replay.is_some() => Network::Offline,
+        (Some(host), _) => {
+            let render_delay = Duration::from_millis(args.render_delay);
+            Network::Client(Client::connect(host, args.port, manifest.clone(), conditions, render_delay)?)
+        },
+        (None, true) => Network::Server(Server::bind(args.port, &current_map, manifest.clone(), conditions)?),
+        (None, false) => Network::Offline,
+    };
+
+    // Clients mirror the server's world rather than simulating their own
     let mut world = World::default();
+    let mut local_players = vec![];
+    if !matches!(network, Network::Client(_)) {
+        spawner::spawn_map_entities(&mut world, &map);
+        local_players.push(spawn_local_player(&mut world, &loadout, 0));
+    }
 
-    let player = world.push((
-        components::Model(1),
-        PlayerBrain,
-        Position(Point3::origin()),
-        Rotation(UnitQuaternion::identity()),
-        Velocity(Vector3::zeros()),
-        Speed(5.64),
-    ));
+    // Offline sessions can be recorded, as long as we aren't already watching one
+    let mut recording = match (&network, &replay) {
+        (Network::Offline, None) if args.record => {
+            Some(Replay::new(&addon_name, &current_map, seed, tick_rate, manifest))
+        },
+        (_, None) if args.record => {
+            warn!("Only offline sessions can be recorded");
+            None
+        },
+        _ => None,
+    };
+    let mut playback = replay.map(Playback::new);
 
-    let mut resources = Resources::default();
-    resources.insert(Camera::default());
-    resources.insert(instance_sender);
+    let mut resources = create_resources(&map, zombie_model, seed)?;
+    resources.insert(Cameras(vec![Camera::default()]));
+    resources.insert(RenderTime::default());
+    resources.insert(RenderList::default());
 
-    let mut logic_scheduler = Schedule::builder()
-        .add_system(update_player_velocities_system())
-        .add_system(update_positions_system())
+    let mut logic_scheduler = match network {
+        Network::Client(_) => Schedule::builder()
+            .add_system(store_previous_transforms_system())
+            .build(),
+        _ => add_gameplay_systems(Schedule::builder().add_system(store_previous_transforms_system())).build(),
+    };
+
+    let mut render_scheduler = Schedule::builder()
+        .add_system(interpolate_snapshots_system())
         .add_system(render_models_system())
         .add_system(update_player_camera_system())
         .build();
 
+    let mut timestep = FixedTimestep::new(tick_rate);
+
     let mut frame_count = 0;
     event_loop.run(move |event, _, control_flow| {
         *control_flow = ControlFlow::Poll;
@@ -148,10 +340,30 @@ async fn main() -> Result<()> {
         match event {
             Event::WindowEvent { event, .. } => match event {
                 WindowEvent::Resized(new_size) => resolution = new_size.into(),
+                WindowEvent::MouseInput { state, button, .. } => input.update_mouse_button_state(button, state),
                 WindowEvent::CloseRequested => {
+                    if let Network::Client(client) = &mut network {
+                        client.disconnect();
+                    }
+
+                    // Remember the window's size for next time
+                    if video.window_mode == WindowMode::Windowed && resolution != video.resolution() {
+                        let saved = settings_file.update(|settings| {
+                            settings.video.width = resolution.width;
+                            settings.video.height = resolution.height;
+                        });
+                        if let Err(e) = saved {
+                            error!("Failed to save settings: {e}");
+                        }
+                    }
+
                     info!("average fps: {}", frame_count / time.elapsed_time().0 as u32);
                     *control_flow = ControlFlow::Exit;
                 },
+                WindowEvent::Focused(f) => {
+                    focused = f;
+                    grab_cursor(&window, focused);
+                },
                 WindowEvent::ScaleFactorChanged { scale_factor: sf, new_inner_size } => {
                     scale_factor = sf;
                     resolution = (*new_inner_size).into();
@@ -159,21 +371,194 @@ async fn main() -> Result<()> {
                 _ => (),
             },
             Event::DeviceEvent { event, .. } => match event {
-                DeviceEvent::MouseMotion { delta } => input.apply_mouse_delta(delta),
+                DeviceEvent::MouseMotion { delta } if focused => input.apply_mouse_delta(delta),
                 DeviceEvent::Key(key_state) => input.update_key_state(key_state),
                 _ => (),
             }
+            // However we're leaving, don't lose the session being recorded
+            Event::LoopDestroyed => {
+                if let Some(recording) = recording.take() {
+                    match recording.save(&replays_dir) {
+                        Ok(path) => info!("Saved replay to {}", path.display()),
+                        Err(e) => error!("Failed to save replay: {e}"),
+                    }
+                }
+            },
             Event::MainEventsCleared => {
-                let input = input.get_state();
-                let mut camera: Camera = *resources.get_mut().unwrap();
-                camera.rotation = input.view_direction;
+                // Pick up any settings edited while we're running
+                match settings_file.poll() {
+                    Some(Ok(settings)) => {
+                        let new = settings.video;
+                        if (new.width, new.height, new.window_mode, new.monitor, new.display_mode)
+                            != (video.width, video.height, video.window_mode, video.monitor, video.display_mode)
+                        {
+                            apply_window_mode(&window, &new);
+                        }
+                        if new.present_mode != video.present_mode {
+                            graphics.set_present_mode(new.present_mode.into());
+                        }
+                        video = new;
+
+                        input.set_bindings(settings.bindings);
+                        input.set_mouse_settings(settings.mouse);
+                        input.set_gamepad_settings(settings.gamepad);
+                    },
+                    Some(Err(e)) => error!("Failed to reload settings: {e}"),
+                    None => (),
+                }
+
+                input.update();
+
+                // Connecting another gamepad brings in another player, unless we're online or in a replay
+                let can_join = !matches!(network, Network::Client(_)) && recording.is_none() && playback.is_none();
+                while can_join && local_players.len() < input.player_count() {
+                    info!("Player {} joined", local_players.len() + 1);
+                    local_players.push(spawn_local_player(&mut world, &loadout, local_players.len()));
+                }
 
-                resources.insert(time.elapsed_time());
-                resources.insert(time.delta_time());
-                resources.insert(input);
-                logic_scheduler.execute(&mut world, &mut resources);
+                // Apply any one-off actions before working out how many ticks to run
+                {
+                    // The network is only serviced on ticks, so online play can't be paused or slowed
+                    let online = !matches!(network, Network::Offline);
+                    let mut time_control = resources.get_mut::<TimeControl>().unwrap();
+                    for action in input.take_pressed_actions() {
+                        match action {
+                            Action::ToggleFullscreen => {
+                                video.window_mode = match video.window_mode {
+                                    WindowMode::Windowed => WindowMode::Borderless,
+                                    _ => WindowMode::Windowed,
+                                };
+                                apply_window_mode(&window, &video);
 
-                if let Err(e) = graphics.render(resolution, &camera) {
+                                let window_mode = video.window_mode;
+                                let saved = settings_file.update(|settings| settings.video.window_mode = window_mode);
+                                if let Err(e) = saved {
+                                    error!("Failed to save settings: {e}");
+                                }
+                            },
+                            Action::Pause | Action::Step | Action::SlowDown | Action::SpeedUp if online => {
+                                info!("Time can't be controlled during online play");
+                            },
+                            Action::Pause if time_control.paused => time_control.resume(),
+                            Action::Pause => time_control.pause(),
+                            Action::Step => time_control.step(),
+                            Action::SlowDown => time_control.scale_by(0.5),
+                            Action::SpeedUp => time_control.scale_by(2.0),
+                            _ => (),
+                        }
+                    }
+
+                    timestep.accumulate(time.delta_time(), &mut time_control);
+                }
+
+                let inputs: Vec<InputState> = (0..input.player_count())
+                    .map(|player| input.get_state(player))
+                    .collect();
+
+                // Catch the simulation up on however many ticks have passed since the last frame
+                while timestep.tick() {
+                    let mut tick_inputs = inputs.clone();
+
+                    // Replays stand in for the first player's input until they run out
+                    if let Some(playback) = &mut playback {
+                        match playback.next_input() {
+                            Some(tick_input) => tick_inputs[0] = tick_input,
+                            None => {
+                                info!("Replay finished");
+                                resources.get_mut::<TimeControl>().unwrap().pause();
+                                break;
+                            },
+                        }
+                    }
+                    let tick_input = tick_inputs[0];
+
+                    if let Some(recording) = &mut recording {
+                        recording.record(tick_input);
+                    }
+
+                    // Drive our own players with our input
+                    for (player, tick_input) in local_players.iter().zip(&tick_inputs) {
+                        if let Some(mut entry) = world.entry(*player) {
+                            if let Ok(state) = entry.get_component_mut::<InputState>() {
+                                *state = *tick_input;
+                            }
+                        }
+                    }
+
+                    // Remote players' input goes in before the tick runs, the same as on a dedicated server
+                    if let Network::Server(server) = &mut network {
+                        let delta_time = timestep.delta_time().0;
+                        server.receive(&mut world, &resources.get::<MapCollider>().unwrap(), &loadout, delta_time);
+                    }
+
+                    resources.insert(time.elapsed_time());
+                    resources.insert(timestep.delta_time());
+                    logic_scheduler.execute(&mut world, &mut resources);
+
+                    match &mut network {
+                        Network::Offline => (),
+                        Network::Server(server) => {
+                            server.assign_network_ids(&mut world);
+                            server.send_snapshot(&world);
+                        },
+                        Network::Client(client) => {
+                            // Our own player is predicted against the map rather than waiting on the server
+                            {
+                                let map_collider = resources.get::<MapCollider>().unwrap();
+                                if let Err(e) = client.receive(&mut world, &map_collider) {
+                                    error!("{e}");
+                                    *control_flow = ControlFlow::Exit;
+                                    return;
+                                }
+                                client.send_input(&mut world, &map_collider, &tick_input, timestep.delta_time().0);
+                            }
+
+                            // Follow the server onto whatever map it's playing
+                            if let Some(map) = client.take_pending_map().filter(|map| *map != current_map) {
+                                match show_map(&map, &content, &mut resources, &mut graphics) {
+                                    Ok(()) => current_map = map,
+                                    Err(e) => error!("Failed to load {map}: {e}"),
+                                }
+                            }
+                        },
+                    }
+
+                    // Switch maps if a player entered a warp zone
+                    let destination = resources.get_mut::<PendingWarp>().and_then(|mut warp| warp.0.take());
+                    if let Some(destination) = destination {
+                        match warp(&destination, &content, &mut world, &mut resources, Some(&mut graphics)) {
+                            Ok(()) => {
+                                if let Network::Server(server) = &mut network {
+                                    server.change_map(&destination);
+                                }
+                                current_map = destination;
+                            },
+                            Err(e) => error!("Failed to warp to {destination}: {e}"),
+                        }
+                    }
+                }
+
+                // Draw everything part way between the last two ticks
+                if let Network::Client(client) = &network {
+                    resources.insert(client.render_time());
+                }
+                resources.insert(timestep.alpha());
+                resources.get_mut::<RenderList>().unwrap().clear();
+                render_scheduler.execute(&mut world, &mut resources);
+
+                // Everyone looks wherever they're looking, unless we're watching someone else's run
+                let mut cameras = resources.get::<Cameras>().unwrap().0.clone();
+                for camera in &mut cameras {
+                    camera.fov = video.fov.to_radians();
+                }
+                if playback.is_none() {
+                    for (camera, player_input) in cameras.iter_mut().zip(&inputs) {
+                        camera.rotation = player_input.view_direction;
+                    }
+                }
+
+                let render_list = resources.get::<RenderList>().unwrap();
+                if let Err(e) = graphics.render(resolution, &cameras, &render_list) {
                     error!("{e}");
                     *control_flow = ControlFlow::Exit;
                 }
@@ -184,3 +569,203 @@ async fn main() -> Result<()> {
         }
     });
 }
+
+// Runs a server without a window, ticking the simulation at a fixed rate until interrupted
+async fn run_dedicated(
+    mut server: Server,
+    content: &Content,
+    map: &Map<'_>,
+    loadout: &Loadout,
+    zombie_model: Option<components::Model>,
+    tick_rate: u32,
+    seed: u64,
+) -> Result<()> {
+    let mut world = World::default();
+    spawner::spawn_map_entities(&mut world, map);
+
+    let mut resources = create_resources(map, zombie_model, seed)?;
+    let mut logic_scheduler = add_gameplay_systems(&mut Schedule::builder()).build();
+
+    let mut timestep = FixedTimestep::new(tick_rate);
+    let mut ticks = tokio::time::interval(Duration::from_secs_f32(timestep.delta_time().0));
+    let time = Time::new();
+
+    let shutdown = tokio::signal::ctrl_c();
+    tokio::pin!(shutdown);
+
+    info!("Running dedicated at {tick_rate} ticks per second");
+    loop {
+        tokio::select! {
+            _ = ticks.tick() => (),
+            _ = &mut shutdown => {
+                info!("Shutting down");
+                return Ok(());
+            },
+        }
+
+        // Time control still applies without a window, it's just driven by gameplay
+        timestep.accumulate(timestep.delta_time(), &mut resources.get_mut::<TimeControl>().unwrap());
+
+        while timestep.tick() {
+            let delta_time = timestep.delta_time().0;
+            server.receive(&mut world, &resources.get::<MapCollider>().unwrap(), loadout, delta_time);
+
+            resources.insert(time.elapsed_time());
+            resources.insert(timestep.delta_time());
+            logic_scheduler.execute(&mut world, &mut resources);
+
+            server.assign_network_ids(&mut world);
+            server.send_snapshot(&world);
+
+            // Switch maps if a player entered a warp zone
+            let destination = resources.get_mut::<PendingWarp>().and_then(|mut warp| warp.0.take());
+            if let Some(destination) = destination {
+                match warp(&destination, content, &mut world, &mut resources, None) {
+                    Ok(()) => server.change_map(&destination),
+                    Err(e) => error!("Failed to warp to {destination}: {e}"),
+                }
+            }
+        }
+    }
+}
+
+// Helper function for spawning a player controlled on this machine
+fn spawn_local_player(world: &mut World, loadout: &Loadout, index: usize) -> Entity {
+    let spawn_position = spawner::find_player_spawn(world).unwrap_or_else(|| {
+        warn!("No player_spawn in map, spawning at the origin");
+        Point3::origin()
+    });
+
+    let player = spawner::spawn_player(world, spawn_position, loadout);
+    if let Some(mut entry) = world.entry(player) {
+        entry.add_component(LocalPlayer(index));
+    }
+
+    player
+}
+
+// Helper function for creating the resources every simulated world needs
+fn create_resources(map: &Map<'_>, zombie_model: Option<components::Model>, seed: u64) -> Result<Resources> {
+    let mut resources = Resources::default();
+    resources.insert(MapCollider::from_map(map)?);
+    resources.insert(WaveController::new(zombie_model));
+    resources.insert(StdRng::seed_from_u64(seed));
+    resources.insert(Events::<DamageEvent>::default());
+    resources.insert(Events::<DeathEvent>::default());
+    resources.insert(Score::default());
+    resources.insert(PendingWarp::default());
+    resources.insert(TimeControl::default());
+    Ok(resources)
+}
+
+// Helper function for adding the systems that simulate the game, which clients leave to the server
+fn add_gameplay_systems(builder: &mut Builder) -> &mut Builder {
+    builder
+        .add_system(spawn_zombies_system())
+        .add_system(update_player_rotations_system())
+        .add_system(update_player_velocities_system())
+        .add_system(update_zombie_velocities_system())
+        .add_system(apply_gravity_system())
+        .add_system(fire_weapons_system())
+        .add_system(update_projectiles_system())
+        .add_system(apply_damage_system())
+        .add_system(update_score_system())
+        .add_system(update_positions_system())
+        .add_system(update_colliding_positions_system())
+        .add_system(update_warp_zones_system())
+}
+
+// Helper function for loading the map a warp zone leads to
+fn warp(
+    destination: &str,
+    content: &Content,
+    world: &mut World,
+    resources: &mut Resources,
+    graphics: Option<&mut Graphics>,
+) -> Result<()> {
+    let map_data = level::read_map(content, destination)?;
+    let map = Map::from_str(&map_data)?;
+    level::change_map(world, resources, &map)?;
+    if let Some(graphics) = graphics {
+        graphics.load_map(&map);
+    }
+    info!("Warped to {destination}");
+    Ok(())
+}
+
+// Helper function for loading just the geometry of a map, used by clients following the server
+fn show_map(
+    name: &str,
+    content: &Content,
+    resources: &mut Resources,
+    graphics: &mut Graphics,
+) -> Result<()> {
+    let map_data = level::read_map(content, name)?;
+    let map = Map::from_str(&map_data)?;
+    resources.insert(MapCollider::from_map(&map)?);
+    graphics.load_map(&map);
+    Ok(())
+}
+
+// Helper function for capturing the cursor while the window has focus, so looking around can't leave the window
+fn grab_cursor(window: &Window, grab: bool) {
+    if let Err(e) = window.set_cursor_grab(grab) {
+        warn!("Failed to {} the cursor: {e}", if grab { "grab" } else { "release" });
+    }
+    window.set_cursor_visible(!grab);
+}
+
+// Helper function for working out which fullscreen mode the window should be in, if any
+fn fullscreen(
+    video: &VideoSettings,
+    mut monitors: impl Iterator<Item = MonitorHandle>,
+    primary: Option<MonitorHandle>,
+) -> Option<Fullscreen> {
+    let monitor = match video.monitor {
+        Some(index) => monitors.nth(index).or(primary),
+        None => primary,
+    };
+
+    match video.window_mode {
+        WindowMode::Windowed => None,
+        WindowMode::Borderless => Some(Fullscreen::Borderless(monitor)),
+        WindowMode::Exclusive => {
+            let video_modes: Vec<VideoMode> = monitor.iter().flat_map(MonitorHandle::video_modes).collect();
+            let video_mode = match video.display_mode {
+                Some(mode) => video_modes.into_iter().find(|video_mode| {
+                    video_mode.size() == PhysicalSize::new(mode.width, mode.height)
+                        && video_mode.refresh_rate() == mode.refresh_rate
+                }),
+                None => video_modes.into_iter().max_by_key(|video_mode| {
+                    let size = video_mode.size();
+                    (size.width * size.height, video_mode.refresh_rate(), video_mode.bit_depth())
+                }),
+            };
+
+            match video_mode {
+                Some(video_mode) => Some(Fullscreen::Exclusive(video_mode)),
+                None => {
+                    warn!("Display mode not available, falling back to borderless fullscreen");
+                    Some(Fullscreen::Borderless(monitor))
+                },
+            }
+        },
+    }
+}
+
+// Helper function for switching the window into the mode in the video settings while the game is running
+fn apply_window_mode(window: &Window, video: &VideoSettings) {
+    window.set_fullscreen(fullscreen(video, window.available_monitors(), window.primary_monitor()));
+    if video.window_mode == WindowMode::Windowed {
+        window.set_inner_size(PhysicalSize::new(video.width, video.height));
+    }
+}
+
+// Helper function for parsing `--tick-rate`, which has to be at least one tick per second
+fn parse_tick_rate(value: &str) -> std::result::Result<u32, String> {
+    match value.parse::<u32>() {
+        Ok(0) => Err("must be at least 1".to_string()),
+        Ok(tick_rate) => Ok(tick_rate),
+        Err(e) => Err(e.to_string()),
+    }
+}
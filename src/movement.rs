@@ -0,0 +1,80 @@
+use nalgebra::Point3;
+use nalgebra::Vector3;
+use nalgebra::vector;
+use parry3d::shape::Shape;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::input::InputState;
+use crate::physics;
+
+/// The stats that shape how a player moves
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Movement {
+    pub speed: f32,
+    pub jump_speed: f32,
+    pub air_control: f32,
+    pub gravity: f32,
+}
+
+/// Where a player is and how they're moving
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PlayerState {
+    pub position: Point3<f32>,
+    pub velocity: Vector3<f32>,
+    pub grounded: bool,
+}
+
+/// Steers `velocity` towards the direction `input` wants to move in, jumping if asked to
+pub fn steer(
+    input: &InputState,
+    speed: f32,
+    jump_speed: f32,
+    air_control: f32,
+    grounded: bool,
+    velocity: &mut Vector3<f32>,
+    delta_time: f32,
+) {
+    let dir = input.move_direction;
+    let target = vector![dir.x, 0.0, dir.y].scale(speed);
+    let current = vector![velocity.x, 0.0, velocity.z];
+
+    // We have full control on the ground, but can only steer gradually through the air
+    let horizontal = match grounded {
+        true => target,
+        false => current + (target - current).scale((air_control * delta_time).min(1.0)),
+    };
+
+    velocity.x = horizontal.x;
+    velocity.z = horizontal.z;
+
+    if grounded && input.jump {
+        velocity.y = jump_speed;
+    }
+}
+
+/// Moves a player by a single input, doing everything the movement systems do to them over a frame
+///
+/// This is what lets the server and clients run a player's input outside of the schedule
+pub fn step(
+    map: &dyn Shape,
+    shape: &dyn Shape,
+    movement: &Movement,
+    input: &InputState,
+    state: &mut PlayerState,
+    delta_time: f32,
+) {
+    steer(
+        input,
+        movement.speed,
+        movement.jump_speed,
+        movement.air_control,
+        state.grounded,
+        &mut state.velocity,
+        delta_time,
+    );
+
+    state.velocity.y -= movement.gravity * delta_time;
+    physics::move_and_slide(map, shape, &mut state.position, &mut state.velocity, delta_time);
+    state.grounded = physics::is_grounded(map, shape, &state.position);
+}
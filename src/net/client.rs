@@ -0,0 +1,374 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
+use std::time::Duration;
+use std::time::Instant;
+
+use legion::Entity;
+use legion::World;
+use parry3d::shape::SharedShape;
+use tracing::info;
+use tracing::warn;
+
+use crate::addon::Manifest;
+use crate::components::LocalPlayer;
+use crate::components::Model;
+use crate::components::NetworkId;
+use crate::components::PlayerBrain;
+use crate::components::Position;
+use crate::components::Rotation;
+use crate::error::Error;
+use crate::input::InputState;
+use crate::interpolation::RenderTime;
+use crate::interpolation::SnapshotBuffer;
+use crate::movement;
+use crate::movement::Movement;
+use crate::movement::PlayerState;
+use crate::physics;
+use crate::physics::MapCollider;
+
+use super::ClientMessage;
+use super::InputCommand;
+use super::Link;
+use super::LinkConditions;
+use super::MAX_INPUT_DELTA;
+use super::MAX_PACKET_SIZE;
+use super::PlayerSnapshot;
+use super::ServerMessage;
+use super::Snapshot;
+use super::protocol;
+
+/// How long we wait for a welcome before asking the server again
+const CONNECT_RETRY_INTERVAL: Duration = Duration::from_millis(500);
+/// The most inputs we keep around waiting for the server to apply them
+const MAX_PENDING_INPUTS: usize = 256;
+
+/// The client side of online play, sending our input and mirroring the server's world
+///
+/// Our own player is predicted from our input rather than waiting on the server, and corrected
+/// whenever a snapshot says where the server has it
+pub struct Client {
+    link: Link,
+    server: SocketAddr,
+    network_id: Option<u32>,
+    last_connect_attempt: Option<Instant>,
+    last_tick: Option<u32>,
+    /// The parts of the newest tick's snapshot that have arrived
+    tick_parts: HashSet<u16>,
+    /// The network ids of every entity in those parts
+    tick_entities: HashSet<u32>,
+    entities: HashMap<u32, Entity>,
+    /// The map the server last told us it's playing
+    map: Option<String>,
+    pending_map: Option<String>,
+    manifest: Manifest,
+    sequence: u32,
+    /// Inputs we've sent that the server hasn't applied yet, oldest first
+    pending_inputs: VecDeque<InputCommand>,
+    /// Our player's movement stats and predicted state, unknown until the server first tells us
+    prediction: Option<(Movement, PlayerState)>,
+    shape: SharedShape,
+    /// How far behind the server remote entities are shown, so there's usually a snapshot either side
+    render_delay: Duration,
+    /// The newest server time we know of and when we learnt it
+    server_time: Option<(f32, Instant)>,
+}
+
+impl Client {
+    pub fn connect(
+        host: Ipv4Addr,
+        port: u16,
+        manifest: Manifest,
+        conditions: LinkConditions,
+        render_delay: Duration,
+    ) -> Result<Self, Error> {
+        let link = Link::bind((Ipv4Addr::UNSPECIFIED, 0), conditions)?;
+
+        Ok(Self {
+            link,
+            server: SocketAddr::from((host, port)),
+            network_id: None,
+            last_connect_attempt: None,
+            last_tick: None,
+            tick_parts: HashSet::new(),
+            tick_entities: HashSet::new(),
+            entities: HashMap::new(),
+            map: None,
+            pending_map: None,
+            manifest,
+            sequence: 0,
+            pending_inputs: VecDeque::new(),
+            prediction: None,
+            shape: physics::humanoid_shape(),
+            render_delay,
+            server_time: None,
+        })
+    }
+
+    /// Sends this frame's input and predicts where it takes our player,
+    /// or keeps asking to join if the server hasn't welcomed us yet
+    pub fn send_input(
+        &mut self,
+        world: &mut World,
+        map_collider: &MapCollider,
+        input: &InputState,
+        delta_time: f32,
+    ) {
+        if self.network_id.is_none() {
+            let retry = self.last_connect_attempt.map_or(true, |last| last.elapsed() > CONNECT_RETRY_INTERVAL);
+            if retry {
+                self.last_connect_attempt = Some(Instant::now());
+                self.send(&ClientMessage::Connect(self.manifest.clone()));
+            }
+            return;
+        }
+
+        self.sequence += 1;
+        let command = InputCommand {
+            sequence: self.sequence,
+            input: *input,
+            delta_time: delta_time.clamp(0.0, MAX_INPUT_DELTA),
+        };
+        self.send(&ClientMessage::Input(command));
+
+        // Keep hold of it in case the server corrects us and we need to play it again
+        self.pending_inputs.push_back(command);
+        if self.pending_inputs.len() > MAX_PENDING_INPUTS {
+            self.pending_inputs.pop_front();
+        }
+
+        if let Some((movement, state)) = &mut self.prediction {
+            movement::step(&*map_collider.0, &*self.shape, movement, input, state, command.delta_time);
+        }
+
+        self.show_prediction(world, input);
+    }
+
+    /// Handles every message received since the last call, mirroring snapshots into the world
+    ///
+    /// Fails if the server refused us for having different content
+    pub fn receive(&mut self, world: &mut World, map_collider: &MapCollider) -> Result<(), Error> {
+        self.link.flush();
+
+        let mut buffer = vec![0; MAX_PACKET_SIZE];
+        loop {
+            let len = match self.link.recv_from(&mut buffer) {
+                Ok((len, address)) if address == self.server => len,
+                Ok(_) => continue,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("Failed to receive from server: {e}");
+                    continue;
+                },
+            };
+
+            match protocol::decode::<ServerMessage>(&buffer[..len]) {
+                Ok(message) => self.handle_message(world, map_collider, message)?,
+                Err(e) => warn!("Dropping malformed packet from server: {e}"),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The server time remote entities should be shown at right now
+    pub fn render_time(&self) -> RenderTime {
+        match self.server_time {
+            Some((time, received)) => {
+                RenderTime(time + received.elapsed().as_secs_f32() - self.render_delay.as_secs_f32())
+            },
+            None => RenderTime::default(),
+        }
+    }
+
+    /// Takes the map the server wants us on, if it has changed
+    pub fn take_pending_map(&mut self) -> Option<String> {
+        self.pending_map.take()
+    }
+
+    pub fn disconnect(&mut self) {
+        self.send(&ClientMessage::Disconnect);
+    }
+
+    fn handle_message(
+        &mut self,
+        world: &mut World,
+        map_collider: &MapCollider,
+        message: ServerMessage,
+    ) -> Result<(), Error> {
+        match message {
+            ServerMessage::Welcome { network_id, map } => {
+                if self.network_id.is_some() {
+                    return Ok(());
+                }
+
+                info!("Connected to {}", self.server);
+                self.network_id = Some(network_id);
+                self.follow_map(map);
+
+                // Our player may have already arrived in a snapshot that beat the welcome here
+                let entity = self.entities.get(&network_id).copied();
+                if let Some(mut entry) = entity.and_then(|entity| world.entry(entity)) {
+                    entry.add_component(PlayerBrain);
+                    entry.add_component(LocalPlayer(0));
+                }
+            },
+            ServerMessage::Rejected(manifest) => {
+                return Err(Error::ContentMismatch(self.manifest.mismatches(&manifest)));
+            },
+            ServerMessage::Snapshot(snapshot) => {
+                // Snapshots can arrive out of order, only the newest matters
+                if self.last_tick.map_or(false, |tick| snapshot.tick < tick) {
+                    return Ok(());
+                }
+
+                if self.last_tick != Some(snapshot.tick) {
+                    self.last_tick = Some(snapshot.tick);
+                    self.tick_parts.clear();
+                    self.tick_entities.clear();
+                }
+
+                // Ignore parts we've already got
+                if !self.tick_parts.insert(snapshot.part) {
+                    return Ok(());
+                }
+
+                self.follow_map(snapshot.map.clone());
+
+                // Late packets would drag our idea of the server's clock back, so only ever move it forwards
+                let estimate = self.server_time.map(|(time, received)| time + received.elapsed().as_secs_f32());
+                if estimate.map_or(true, |estimate| snapshot.time > estimate) {
+                    self.server_time = Some((snapshot.time, Instant::now()));
+                }
+
+                // Only the first part says anything about our own player
+                if snapshot.part == 0 {
+                    match &snapshot.player {
+                        Some(player) => self.reconcile(map_collider, player),
+                        None => self.prediction = None,
+                    }
+                }
+                self.apply_snapshot(world, &snapshot);
+            },
+        }
+
+        Ok(())
+    }
+
+    // Helper function for noting the server's map, queueing it for loading if it's changed
+    fn follow_map(&mut self, map: String) {
+        if self.map.as_ref() != Some(&map) {
+            self.map = Some(map.clone());
+            self.pending_map = Some(map);
+        }
+    }
+
+    // Rewinds our player to where the server has them and replays every input it hasn't applied yet
+    fn reconcile(&mut self, map_collider: &MapCollider, player: &PlayerSnapshot) {
+        while self.pending_inputs.front().map_or(false, |command| command.sequence <= player.acked_input) {
+            self.pending_inputs.pop_front();
+        }
+
+        let mut state = player.state;
+        for command in &self.pending_inputs {
+            let input = &command.input;
+            movement::step(&*map_collider.0, &*self.shape, &player.movement, input, &mut state, command.delta_time);
+        }
+
+        self.prediction = Some((player.movement, state));
+    }
+
+    // Moves our player to where we predict they are, looking wherever we're looking
+    fn show_prediction(&self, world: &mut World, input: &InputState) {
+        let (network_id, (_, state)) = match (self.network_id, &self.prediction) {
+            (Some(network_id), Some(prediction)) => (network_id, prediction),
+            _ => return,
+        };
+
+        let entity = self.entities.get(&network_id).copied();
+        if let Some(mut entry) = entity.and_then(|entity| world.entry(entity)) {
+            entry.add_component(Position(state.position));
+            entry.add_component(Rotation(input.view_direction));
+        }
+    }
+
+    fn apply_snapshot(&mut self, world: &mut World, snapshot: &Snapshot) {
+        for state in &snapshot.entities {
+            let existing = self.entities
+                .get(&state.network_id)
+                .copied()
+                .filter(|entity| world.contains(*entity));
+
+            let entity = match existing {
+                Some(entity) => entity,
+                None => {
+                    let entity = world.push((
+                        NetworkId(state.network_id),
+                        Position(state.position),
+                        Rotation(state.rotation),
+                        SnapshotBuffer::default(),
+                    ));
+
+                    // Our own player drives the camera
+                    if Some(state.network_id) == self.network_id {
+                        if let Some(mut entry) = world.entry(entity) {
+                            entry.add_component(PlayerBrain);
+                            entry.add_component(LocalPlayer(0));
+                        }
+                    }
+
+                    self.entities.insert(state.network_id, entity);
+                    entity
+                },
+            };
+
+            // Our own player stays where we predict them to be
+            let predicted = Some(state.network_id) == self.network_id && self.prediction.is_some();
+
+            if let Some(mut entry) = world.entry(entity) {
+                if let Ok(buffer) = entry.get_component_mut::<SnapshotBuffer>() {
+                    match predicted {
+                        true => buffer.clear(),
+                        false => buffer.push(snapshot.time, state.position, state.rotation),
+                    }
+                }
+                match state.model {
+                    Some(model) => entry.add_component(Model(model)),
+                    None => entry.remove_component::<Model>(),
+                }
+            }
+        }
+
+        self.tick_entities.extend(snapshot.entities.iter().map(|state| state.network_id));
+
+        // Anything missing from a whole snapshot no longer exists on the server, which we can only tell once
+        // every part of it is here
+        if self.tick_parts.len() < snapshot.parts as usize {
+            return;
+        }
+
+        let stale: Vec<u32> = self.entities
+            .keys()
+            .filter(|network_id| !self.tick_entities.contains(*network_id))
+            .copied()
+            .collect();
+
+        for network_id in stale {
+            if let Some(entity) = self.entities.remove(&network_id) {
+                world.remove(entity);
+            }
+        }
+    }
+
+    fn send(&mut self, message: &ClientMessage) {
+        let result = protocol::encode(message)
+            .and_then(|bytes| self.link.send_to(bytes, self.server));
+
+        if let Err(e) = result {
+            warn!("Failed to send to server: {e}");
+        }
+    }
+}
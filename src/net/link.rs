@@ -0,0 +1,91 @@
+use std::collections::VecDeque;
+use std::io;
+use std::net::SocketAddr;
+use std::net::ToSocketAddrs;
+use std::net::UdpSocket;
+use std::time::Duration;
+use std::time::Instant;
+
+use rand::Rng;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use tracing::info;
+use tracing::warn;
+
+use crate::error::Error;
+
+/// A bad connection to simulate on outgoing packets, for trying out online play over loopback
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct LinkConditions {
+    /// How long every packet is held back before being sent
+    pub latency: Duration,
+    /// The fraction of packets that are dropped, between 0 and 1
+    pub loss: f32,
+    /// Seeds which packets are dropped so the same ones are every run, different every run if unset
+    pub seed: Option<u64>,
+}
+
+/// A nonblocking UDP socket that can pretend to be a worse connection than it is
+pub struct Link {
+    socket: UdpSocket,
+    conditions: LinkConditions,
+    delayed: VecDeque<(Instant, SocketAddr, Vec<u8>)>,
+    rng: StdRng,
+}
+
+impl Link {
+    pub fn bind<A: ToSocketAddrs>(address: A, conditions: LinkConditions) -> Result<Self, Error> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+
+        if !conditions.latency.is_zero() || conditions.loss > 0.0 {
+            let latency = conditions.latency.as_millis();
+            info!("Simulating {latency}ms of latency and {}% packet loss", conditions.loss * 100.0);
+        }
+
+        Ok(Self {
+            socket,
+            conditions,
+            delayed: VecDeque::new(),
+            rng: conditions.seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64),
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    pub fn recv_from(&self, buffer: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        self.socket.recv_from(buffer)
+    }
+
+    /// Sends `bytes` to `address`, unless the simulated conditions hold it back or lose it
+    pub fn send_to(&mut self, bytes: Vec<u8>, address: SocketAddr) -> Result<(), Error> {
+        if self.rng.gen::<f32>() < self.conditions.loss {
+            return Ok(());
+        }
+
+        if self.conditions.latency.is_zero() {
+            self.socket.send_to(&bytes, address)?;
+        } else {
+            self.delayed.push_back((Instant::now() + self.conditions.latency, address, bytes));
+        }
+
+        Ok(())
+    }
+
+    /// Sends every held back packet whose latency has passed
+    pub fn flush(&mut self) {
+        while let Some((due, _, _)) = self.delayed.front() {
+            if *due > Instant::now() {
+                break;
+            }
+
+            if let Some((_, address, bytes)) = self.delayed.pop_front() {
+                if let Err(e) = self.socket.send_to(&bytes, address) {
+                    warn!("Failed to send to {address}: {e}");
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,192 @@
+mod client;
+mod link;
+mod protocol;
+mod server;
+
+pub use self::client::Client;
+pub use self::link::LinkConditions;
+pub use self::server::Server;
+
+use self::link::Link;
+use self::protocol::ClientMessage;
+use self::protocol::EntitySnapshot;
+use self::protocol::InputCommand;
+use self::protocol::PlayerSnapshot;
+use self::protocol::ServerMessage;
+use self::protocol::Snapshot;
+
+/// The port servers listen on unless told otherwise
+pub const DEFAULT_PORT: u16 = 27015;
+
+/// The longest frame a player may be moved by, so a stall can't launch them through the map
+const MAX_INPUT_DELTA: f32 = 0.25;
+
+/// The largest datagram we expect to send or receive
+const MAX_PACKET_SIZE: usize = 65507;
+
+/// The part this instance of the game plays in online play
+pub enum Network {
+    Offline,
+    /// Simulates the world and replicates it to clients
+    Server(Server),
+    /// Mirrors the world of a server
+    Client(Client),
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::net::Ipv4Addr;
+    use std::thread;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    use legion::IntoQuery;
+    use legion::World;
+    use nalgebra::Point3;
+    use nalgebra::point;
+    use nalgebra::vector;
+    use parry3d::shape::SharedShape;
+
+    use crate::addon::Manifest;
+    use crate::components::LocalPlayer;
+    use crate::components::Position;
+    use crate::components::RemoteInput;
+    use crate::components::Speed;
+    use crate::input::InputState;
+    use crate::physics::MapCollider;
+    use crate::spawner::Loadout;
+
+    use super::Client;
+    use super::LinkConditions;
+    use super::MAX_INPUT_DELTA;
+    use super::Server;
+
+    const DELTA_TIME: f32 = 1.0 / 60.0;
+    /// How long we'll wait on loopback for something to get through before giving up
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    // A server and a client talking over loopback, both dropping a share of what they send
+    struct Session {
+        server: Server,
+        server_world: World,
+        client: Client,
+        client_world: World,
+        map_collider: MapCollider,
+    }
+
+    impl Session {
+        fn connect(loss: f32) -> Self {
+            let manifest = Manifest { addons: BTreeMap::new(), files: BTreeMap::new() };
+            let server_conditions = LinkConditions { latency: Duration::ZERO, loss, seed: Some(1) };
+            let client_conditions = LinkConditions { seed: Some(2), ..server_conditions };
+
+            let server = Server::bind(0, "test", manifest.clone(), server_conditions).unwrap();
+            let port = server.local_addr().unwrap().port();
+            let client =
+                Client::connect(Ipv4Addr::LOCALHOST, port, manifest, client_conditions, Duration::ZERO).unwrap();
+
+            // A floor a little below the origin, where the player spawns without a map
+            let vertices = vec![
+                point![-100.0, -1.0, -100.0],
+                point![100.0, -1.0, -100.0],
+                point![100.0, -1.0, 100.0],
+                point![-100.0, -1.0, 100.0],
+            ];
+            let map_collider = MapCollider(SharedShape::trimesh(vertices, vec![[0, 2, 1], [0, 3, 2]]));
+
+            let mut session = Self {
+                server,
+                server_world: World::default(),
+                client,
+                client_world: World::default(),
+                map_collider,
+            };
+
+            // Keep going until the welcome and the first word of where our player is both get through
+            let connected = session.wait_until(|session| session.predicted().is_some());
+            assert!(connected, "Never connected");
+            session
+        }
+
+        // Runs idle ticks until `done` or we run out of patience, returning whether it got there
+        fn wait_until(&mut self, done: impl Fn(&Self) -> bool) -> bool {
+            let deadline = Instant::now() + TIMEOUT;
+            while !done(self) {
+                if Instant::now() > deadline {
+                    return false;
+                }
+
+                self.tick(&InputState::default(), 1);
+                thread::sleep(Duration::from_millis(1));
+            }
+
+            true
+        }
+
+        // Runs a tick on the server, then sends `inputs` copies of `input` from the client
+        fn tick(&mut self, input: &InputState, inputs: usize) {
+            self.server.receive(&mut self.server_world, &self.map_collider, &Loadout::default(), DELTA_TIME);
+            self.server.assign_network_ids(&mut self.server_world);
+            self.server.send_snapshot(&self.server_world);
+
+            self.client.receive(&mut self.client_world, &self.map_collider).unwrap();
+            for _ in 0..inputs {
+                self.client.send_input(&mut self.client_world, &self.map_collider, input, DELTA_TIME);
+            }
+        }
+
+        fn authoritative(&self) -> Point3<f32> {
+            let (_, position) = <(&RemoteInput, &Position)>::query().iter(&self.server_world).next().unwrap();
+            position.0
+        }
+
+        fn predicted(&self) -> Option<Point3<f32>> {
+            <(&LocalPlayer, &Position)>::query()
+                .iter(&self.client_world)
+                .next()
+                .map(|(_, position)| position.0)
+        }
+    }
+
+    fn forward() -> InputState {
+        InputState { move_direction: vector![0.0, 1.0], ..InputState::default() }
+    }
+
+    #[test]
+    fn prediction_settles_on_the_server_despite_loss() {
+        let mut session = Session::connect(0.2);
+        let start = session.authoritative();
+
+        for _ in 0..120 {
+            session.tick(&forward(), 1);
+        }
+
+        // Lost inputs never reach the server, so the client has to be pulled back to it once it stands still
+        let settled = session.wait_until(|session| {
+            session.predicted().map_or(false, |predicted| (predicted - session.authoritative()).norm() < 1e-3)
+        });
+
+        let authoritative = session.authoritative();
+        let predicted = session.predicted().unwrap();
+        assert!(authoritative.z - start.z > 5.0, "Barely moved from {start} to {authoritative}");
+        assert!(settled, "Predicted {predicted}, server has {authoritative}");
+    }
+
+    #[test]
+    fn extra_inputs_do_not_speed_players_up() {
+        let mut session = Session::connect(0.0);
+        let start = session.authoritative();
+
+        let ticks = 60;
+        for _ in 0..ticks {
+            session.tick(&forward(), 10);
+        }
+
+        // Allowing for whatever time was banked before we started
+        let (_, speed) = <(&RemoteInput, &Speed)>::query().iter(&session.server_world).next().unwrap();
+        let limit = speed.0 * (DELTA_TIME * ticks as f32 + MAX_INPUT_DELTA);
+        let moved = session.authoritative().z - start.z;
+        assert!(moved <= limit + 1e-3, "Moved {moved} in {ticks} ticks");
+    }
+}
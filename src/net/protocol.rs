@@ -0,0 +1,87 @@
+use nalgebra::Point3;
+use nalgebra::UnitQuaternion;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::addon::Manifest;
+use crate::error::Error;
+use crate::input::InputState;
+use crate::movement::Movement;
+use crate::movement::PlayerState;
+
+/// Messages sent from a client to the server
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum ClientMessage {
+    /// Asks the server for a player, sent until the server welcomes or rejects us
+    Connect(Manifest),
+    /// The client's input for the current frame
+    Input(InputCommand),
+    Disconnect,
+}
+
+/// Messages sent from the server to its clients
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub enum ServerMessage {
+    /// Accepts a client, telling it which networked entity is its player
+    Welcome {
+        network_id: u32,
+        map: String,
+    },
+    /// Refuses a client whose content doesn't match, sending the server's own for comparison
+    Rejected(Manifest),
+    Snapshot(Snapshot),
+}
+
+/// A single frame of a client's input, numbered so the server can tell the client which it has applied
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct InputCommand {
+    pub sequence: u32,
+    pub input: InputState,
+    /// How long the client's frame lasted, which the server moves the player by as far as its own clock allows
+    pub delta_time: f32,
+}
+
+/// The state of every networked entity on a given server tick
+///
+/// Each tick's entities are split across as many parts as it takes for every part to arrive unfragmented,
+/// so losing one part only loses the entities in it
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Snapshot {
+    pub tick: u32,
+    /// Which of this tick's parts this is, counting from 0
+    pub part: u16,
+    /// How many parts this tick's snapshot was split into
+    pub parts: u16,
+    /// Seconds since the server started, which remote entities are interpolated by
+    pub time: f32,
+    /// The map being played, sent every time so clients that miss a change still catch up
+    pub map: String,
+    /// The movement of the receiving client's own player if they're alive, only ever sent in the first part
+    pub player: Option<PlayerSnapshot>,
+    pub entities: Vec<EntitySnapshot>,
+}
+
+/// The authoritative movement of a client's player, used to correct its prediction
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct PlayerSnapshot {
+    /// The sequence number of the last input applied to `state`
+    pub acked_input: u32,
+    pub movement: Movement,
+    pub state: PlayerState,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct EntitySnapshot {
+    pub network_id: u32,
+    pub model: Option<u32>,
+    pub position: Point3<f32>,
+    pub rotation: UnitQuaternion<f32>,
+}
+
+pub fn encode<T: Serialize>(message: &T) -> Result<Vec<u8>, Error> {
+    Ok(bincode::serialize(message)?)
+}
+
+pub fn decode<'a, T: Deserialize<'a>>(bytes: &'a [u8]) -> Result<T, Error> {
+    Ok(bincode::deserialize(bytes)?)
+}
@@ -0,0 +1,373 @@
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
+use std::time::Duration;
+use std::time::Instant;
+
+use legion::Entity;
+use legion::IntoQuery;
+use legion::World;
+use legion::component;
+use nalgebra::Point3;
+use tracing::info;
+use tracing::warn;
+
+use crate::addon::Manifest;
+use crate::components::AirControl;
+use crate::components::Collider;
+use crate::components::Dead;
+use crate::components::Gravity;
+use crate::components::Grounded;
+use crate::components::JumpSpeed;
+use crate::components::Model;
+use crate::components::NetworkId;
+use crate::components::Position;
+use crate::components::RemoteInput;
+use crate::components::Rotation;
+use crate::components::Speed;
+use crate::components::Velocity;
+use crate::error::Error;
+use crate::input::InputState;
+use crate::movement;
+use crate::movement::Movement;
+use crate::movement::PlayerState;
+use crate::physics::MapCollider;
+use crate::spawner;
+use crate::spawner::Loadout;
+
+use super::ClientMessage;
+use super::EntitySnapshot;
+use super::Link;
+use super::LinkConditions;
+use super::MAX_INPUT_DELTA;
+use super::MAX_PACKET_SIZE;
+use super::PlayerSnapshot;
+use super::ServerMessage;
+use super::Snapshot;
+use super::protocol;
+
+/// How long we wait to hear from a client before dropping them
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+/// The most entities sent in each part of a snapshot, keeping parts well under a typical 1200 byte MTU
+const ENTITIES_PER_PART: usize = 24;
+
+struct RemoteClient {
+    entity: Entity,
+    network_id: u32,
+    last_heard: Instant,
+    /// The sequence number of the last input we applied
+    last_input: Option<u32>,
+    /// How much time their input may still move their player by, topped up as our own time passes
+    /// so sending extra inputs can't speed them up
+    movement_budget: f32,
+}
+
+/// The authoritative side of online play, simulating the world and replicating it to clients
+pub struct Server {
+    link: Link,
+    clients: HashMap<SocketAddr, RemoteClient>,
+    next_network_id: u32,
+    tick: u32,
+    started: Instant,
+    map: String,
+    manifest: Manifest,
+}
+
+impl Server {
+    pub fn bind(port: u16, map: &str, manifest: Manifest, conditions: LinkConditions) -> Result<Self, Error> {
+        let link = Link::bind((Ipv4Addr::UNSPECIFIED, port), conditions)?;
+        info!("Listening on {}", link.local_addr()?);
+
+        Ok(Self {
+            link,
+            clients: HashMap::new(),
+            next_network_id: 0,
+            tick: 0,
+            started: Instant::now(),
+            map: map.to_string(),
+            manifest,
+        })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, Error> {
+        self.link.local_addr()
+    }
+
+    /// Handles every message received since the last call, spawning players for new clients
+    /// and moving them by their input
+    ///
+    /// `delta_time` is how long the tick lasted, which is as far as any client's input may move them
+    pub fn receive(&mut self, world: &mut World, map_collider: &MapCollider, loadout: &Loadout, delta_time: f32) {
+        self.link.flush();
+
+        // Bank a little time so inputs bunched up by jitter still get to move the player
+        for client in self.clients.values_mut() {
+            client.movement_budget = (client.movement_budget + delta_time).min(MAX_INPUT_DELTA);
+        }
+
+        let mut buffer = vec![0; MAX_PACKET_SIZE];
+        loop {
+            let (len, address) = match self.link.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    warn!("Failed to receive from client: {e}");
+                    continue;
+                },
+            };
+
+            let message = match protocol::decode::<ClientMessage>(&buffer[..len]) {
+                Ok(message) => message,
+                Err(e) => {
+                    warn!("Dropping malformed packet from {address}: {e}");
+                    continue;
+                },
+            };
+
+            self.handle_message(world, map_collider, loadout, address, message);
+        }
+
+        // Drop anyone we haven't heard from in a while
+        let timed_out: Vec<SocketAddr> = self.clients
+            .iter()
+            .filter(|(_, client)| client.last_heard.elapsed() > CLIENT_TIMEOUT)
+            .map(|(address, _)| *address)
+            .collect();
+
+        for address in timed_out {
+            info!("{address} timed out");
+            self.disconnect(world, address);
+        }
+    }
+
+    /// Gives every replicated entity that doesn't have a network id one
+    pub fn assign_network_ids(&mut self, world: &mut World) {
+        let unassigned: Vec<Entity> = <Entity>::query()
+            .filter(component::<Position>() & component::<Rotation>() & !component::<NetworkId>())
+            .iter(world)
+            .copied()
+            .collect();
+
+        for entity in unassigned {
+            let network_id = self.allocate_network_id();
+            if let Some(mut entry) = world.entry(entity) {
+                entry.add_component(NetworkId(network_id));
+            }
+        }
+    }
+
+    /// Sends the state of every networked entity to every client, along with how far their own player has got
+    ///
+    /// Big worlds are split into several parts so no packet has to be fragmented on the way
+    pub fn send_snapshot(&mut self, world: &World) {
+        self.tick = self.tick.wrapping_add(1);
+        let time = self.started.elapsed().as_secs_f32();
+
+        let entities: Vec<EntitySnapshot> = <(&NetworkId, &Position, &Rotation, Option<&Model>)>::query()
+            .iter(world)
+            .map(|(network_id, position, rotation, model)| EntitySnapshot {
+                network_id: network_id.0,
+                model: model.map(|model| model.0),
+                position: position.0,
+                rotation: rotation.0,
+            })
+            .collect();
+
+        let chunks: Vec<&[EntitySnapshot]> = match entities.is_empty() {
+            true => vec![&[]],
+            false => entities.chunks(ENTITIES_PER_PART).collect(),
+        };
+        let parts = chunks.len() as u16;
+
+        let mut snapshots: Vec<(SocketAddr, ServerMessage)> = vec![];
+        for (address, client) in &self.clients {
+            let mut player = player_snapshot(world, client);
+            for (part, chunk) in chunks.iter().enumerate() {
+                let snapshot = Snapshot {
+                    tick: self.tick,
+                    part: part as u16,
+                    parts,
+                    time,
+                    map: self.map.clone(),
+                    player: player.take(),
+                    entities: chunk.to_vec(),
+                };
+
+                snapshots.push((*address, ServerMessage::Snapshot(snapshot)));
+            }
+        }
+
+        for (address, snapshot) in snapshots {
+            self.send(address, &snapshot);
+        }
+    }
+
+    /// Moves every client on to another map, starting with the next snapshot
+    pub fn change_map(&mut self, map: &str) {
+        self.map = map.to_string();
+    }
+
+    fn handle_message(
+        &mut self,
+        world: &mut World,
+        map_collider: &MapCollider,
+        loadout: &Loadout,
+        address: SocketAddr,
+        message: ClientMessage,
+    ) {
+        match message {
+            ClientMessage::Connect(manifest) => {
+                let network_id = match self.clients.get(&address).map(|client| client.network_id) {
+                    Some(network_id) => network_id,
+                    None => {
+                        // Only let in clients that agree with us on the content being played
+                        let mismatches = self.manifest.mismatches(&manifest);
+                        if !mismatches.is_empty() {
+                            warn!("Rejecting {address}, content differs: {}", mismatches.join(", "));
+                            self.send(address, &ServerMessage::Rejected(self.manifest.clone()));
+                            return;
+                        }
+
+                        let position = spawner::find_player_spawn(world).unwrap_or_else(Point3::origin);
+                        let entity = spawner::spawn_player(world, position, loadout);
+                        let network_id = self.allocate_network_id();
+                        if let Some(mut entry) = world.entry(entity) {
+                            entry.add_component(NetworkId(network_id));
+                            entry.add_component(RemoteInput);
+                        }
+
+                        info!("{address} connected");
+                        self.clients.insert(address, RemoteClient {
+                            entity,
+                            network_id,
+                            last_heard: Instant::now(),
+                            last_input: None,
+                            movement_budget: 0.0,
+                        });
+                        network_id
+                    },
+                };
+
+                // Welcome them again in case the last one got lost
+                let welcome = ServerMessage::Welcome { network_id, map: self.map.clone() };
+                self.send(address, &welcome);
+            },
+            ClientMessage::Input(command) => {
+                let client = match self.clients.get_mut(&address) {
+                    Some(client) => client,
+                    None => return,
+                };
+
+                client.last_heard = Instant::now();
+
+                // Inputs that arrive late or twice have already been overtaken
+                if client.last_input.map_or(false, |last| command.sequence <= last) {
+                    return;
+                }
+                client.last_input = Some(command.sequence);
+
+                if let Some(mut entry) = world.entry(client.entity) {
+                    if let Ok(state) = entry.get_component_mut::<InputState>() {
+                        *state = command.input;
+                    }
+                }
+
+                // However long the client claims its frame was, it can't have more time than we've had
+                let delta_time = command.delta_time.clamp(0.0, MAX_INPUT_DELTA).min(client.movement_budget);
+                client.movement_budget -= delta_time;
+
+                move_player(world, map_collider, client.entity, &command.input, delta_time);
+            },
+            ClientMessage::Disconnect => {
+                info!("{address} disconnected");
+                self.disconnect(world, address);
+            },
+        }
+    }
+
+    fn disconnect(&mut self, world: &mut World, address: SocketAddr) {
+        if let Some(client) = self.clients.remove(&address) {
+            world.remove(client.entity);
+        }
+    }
+
+    fn allocate_network_id(&mut self) -> u32 {
+        let network_id = self.next_network_id;
+        self.next_network_id = self.next_network_id.wrapping_add(1);
+        network_id
+    }
+
+    fn send(&mut self, address: SocketAddr, message: &ServerMessage) {
+        let result = protocol::encode(message)
+            .and_then(|bytes| self.link.send_to(bytes, address));
+
+        if let Err(e) = result {
+            warn!("Failed to send to {address}: {e}");
+        }
+    }
+}
+
+// Moves a living client's player by a single input, the same way the schedule moves local players
+fn move_player(world: &mut World, map_collider: &MapCollider, entity: Entity, input: &InputState, delta_time: f32) {
+    let dead = world.entry_ref(entity).map_or(true, |entry| entry.get_component::<Dead>().is_ok());
+    if dead {
+        return;
+    }
+
+    let mut query = <(
+        &Collider,
+        &Speed,
+        &JumpSpeed,
+        &AirControl,
+        &Gravity,
+        &mut Position,
+        &mut Velocity,
+        &mut Grounded,
+    )>::query();
+
+    let (collider, speed, jump_speed, air_control, gravity, position, velocity, grounded) =
+        match query.get_mut(world, entity) {
+            Ok(components) => components,
+            Err(_) => return,
+        };
+
+    let movement = Movement {
+        speed: speed.0,
+        jump_speed: jump_speed.0,
+        air_control: air_control.0,
+        gravity: gravity.0,
+    };
+
+    let mut state = PlayerState { position: position.0, velocity: velocity.0, grounded: grounded.0 };
+    movement::step(&*map_collider.0, &*collider.0, &movement, input, &mut state, delta_time);
+
+    position.0 = state.position;
+    velocity.0 = state.velocity;
+    grounded.0 = state.grounded;
+}
+
+// The authoritative movement of a client's player, or nothing if they're dead or haven't sent any input yet
+fn player_snapshot(world: &World, client: &RemoteClient) -> Option<PlayerSnapshot> {
+    let acked_input = client.last_input?;
+
+    let entry = world.entry_ref(client.entity).ok()?;
+    if entry.get_component::<Dead>().is_ok() {
+        return None;
+    }
+
+    let movement = Movement {
+        speed: entry.get_component::<Speed>().ok()?.0,
+        jump_speed: entry.get_component::<JumpSpeed>().ok()?.0,
+        air_control: entry.get_component::<AirControl>().ok()?.0,
+        gravity: entry.get_component::<Gravity>().ok()?.0,
+    };
+
+    let state = PlayerState {
+        position: entry.get_component::<Position>().ok()?.0,
+        velocity: entry.get_component::<Velocity>().ok()?.0,
+        grounded: entry.get_component::<Grounded>().ok()?.0,
+    };
+
+    Some(PlayerSnapshot { acked_input, movement, state })
+}
@@ -0,0 +1,227 @@
+use legion::Entity;
+use mappy::Map;
+use nalgebra::Isometry3;
+use nalgebra::Point3;
+use nalgebra::Vector3;
+use nalgebra::point;
+use parry3d::query::Ray;
+use parry3d::query::RayCast;
+use parry3d::query::TOIStatus;
+use parry3d::shape::Shape;
+use parry3d::shape::SharedShape;
+
+use crate::error::Error;
+use crate::spawner::map_to_world;
+
+/// The maximum number of surfaces a single move may slide along
+const MAX_SLIDES: usize = 4;
+/// The distance kept between a moving collider and the surfaces it touches
+const SKIN_WIDTH: f32 = 0.01;
+/// How far below a collider we look for ground to stand on
+const GROUND_PROBE_DISTANCE: f32 = SKIN_WIDTH * 2.0;
+/// The minimum upwards component of a surface normal for it to count as ground
+const MIN_GROUND_NORMAL_Y: f32 = 0.7;
+/// How far outside of a brush plane a point may be while still counting as inside
+const PLANE_EPSILON: f32 = 0.01;
+
+/// The static geometry of the loaded map in world space
+pub struct MapCollider(pub SharedShape);
+
+impl MapCollider {
+    /// Fails if the map has no surfaces to collide with, since a collider needs at least one triangle
+    pub fn from_map(map: &Map<'_>) -> Result<Self, Error> {
+        let vertices = map.vertices.iter().map(|vertex| map_to_world(*vertex)).collect();
+
+        // Each surface is a triangle strip, so we flip every other triangle to keep the winding
+        let mut indices = vec![];
+        let mut start = 0;
+        for &vertex_count in &map.vertex_counts {
+            for i in 0..vertex_count.saturating_sub(2) {
+                let a = start + i;
+                match i % 2 {
+                    0 => indices.push([a, a + 1, a + 2]),
+                    _ => indices.push([a + 1, a, a + 2]),
+                }
+            }
+            start += vertex_count;
+        }
+
+        if indices.is_empty() {
+            return Err(Error::MapWithoutGeometry);
+        }
+
+        Ok(Self(SharedShape::trimesh(vertices, indices)))
+    }
+}
+
+/// Something shots can hit besides the map
+#[derive(Clone, Debug)]
+pub struct Target {
+    pub entity: Entity,
+    pub position: Point3<f32>,
+    pub shape: SharedShape,
+}
+
+/// What a ray hit first
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RayHit {
+    Map,
+    Target(Entity),
+}
+
+/// The collider shape shared by players and zombies, with its base at the entity's position
+pub fn humanoid_shape() -> SharedShape {
+    SharedShape::capsule(point![0.0, 0.4, 0.0], point![0.0, 1.4, 0.0], 0.4)
+}
+
+/// Moves `shape` from `position` by `velocity * delta_time`, sliding along any map geometry it hits
+///
+/// Any part of `velocity` heading into a surface that was hit is removed
+pub fn move_and_slide(
+    map: &dyn Shape,
+    shape: &dyn Shape,
+    position: &mut Point3<f32>,
+    velocity: &mut Vector3<f32>,
+    delta_time: f32,
+) {
+    let mut motion = velocity.scale(delta_time);
+
+    for _ in 0..MAX_SLIDES {
+        let distance = motion.norm();
+        if distance <= f32::EPSILON {
+            break;
+        }
+
+        let hit = parry3d::query::time_of_impact(
+            &Isometry3::translation(position.x, position.y, position.z),
+            &motion,
+            shape,
+            &Isometry3::identity(),
+            &Vector3::zeros(),
+            map,
+            1.0,
+        );
+
+        let hit = match hit {
+            // Already inside the map, so climb back out before going any further
+            Ok(Some(hit)) if hit.status == TOIStatus::Penetrating => match penetration(map, shape, position) {
+                Some((normal, depth)) => {
+                    *position += normal.scale(depth + SKIN_WIDTH);
+                    motion -= normal.scale(motion.dot(&normal).min(0.0));
+                    *velocity -= normal.scale(velocity.dot(&normal).min(0.0));
+                    continue;
+                },
+                None => break,
+            },
+            Ok(Some(hit)) => hit,
+            _ => {
+                *position += motion;
+                break;
+            },
+        };
+
+        // Advance up to the surface, keeping our distance
+        let travel = ((hit.toi * distance - SKIN_WIDTH) / distance).max(0.0);
+        *position += motion.scale(travel);
+
+        // Slide the rest of the way along the surface
+        let normal = hit.normal2.into_inner();
+        motion = motion.scale(1.0 - travel);
+        motion -= normal.scale(motion.dot(&normal).min(0.0));
+        *velocity -= normal.scale(velocity.dot(&normal).min(0.0));
+    }
+}
+
+/// Checks whether `shape` at `position` is standing on map geometry flat enough to stand on
+pub fn is_grounded(map: &dyn Shape, shape: &dyn Shape, position: &Point3<f32>) -> bool {
+    let hit = parry3d::query::time_of_impact(
+        &Isometry3::translation(position.x, position.y, position.z),
+        &Vector3::new(0.0, -GROUND_PROBE_DISTANCE, 0.0),
+        shape,
+        &Isometry3::identity(),
+        &Vector3::zeros(),
+        map,
+        1.0,
+    );
+
+    match hit {
+        Ok(Some(hit)) if hit.status == TOIStatus::Penetrating => {
+            penetration(map, shape, position).map_or(false, |(normal, _)| normal.y >= MIN_GROUND_NORMAL_Y)
+        },
+        Ok(Some(hit)) => hit.normal2.y >= MIN_GROUND_NORMAL_Y,
+        _ => false,
+    }
+}
+
+// Finds which way and how far `shape` at `position` has to move to get out of the map, if it's inside it
+fn penetration(map: &dyn Shape, shape: &dyn Shape, position: &Point3<f32>) -> Option<(Vector3<f32>, f32)> {
+    let contact = parry3d::query::contact(
+        &Isometry3::translation(position.x, position.y, position.z),
+        shape,
+        &Isometry3::identity(),
+        map,
+        0.0,
+    );
+
+    match contact {
+        Ok(Some(contact)) if contact.dist < 0.0 => Some((contact.normal2.into_inner(), -contact.dist)),
+        _ => None,
+    }
+}
+
+/// Casts `ray` against the map and every target, returning the closest hit within `max_toi`
+pub fn cast_ray(map: &dyn Shape, targets: &[Target], ray: &Ray, max_toi: f32) -> Option<RayHit> {
+    let mut closest = map
+        .cast_ray(&Isometry3::identity(), ray, max_toi, true)
+        .map(|toi| (toi, RayHit::Map));
+
+    for target in targets {
+        let max_toi = closest.map_or(max_toi, |(toi, _)| toi);
+        let isometry = Isometry3::translation(target.position.x, target.position.y, target.position.z);
+        if let Some(toi) = target.shape.cast_ray(&isometry, ray, max_toi, true) {
+            closest = Some((toi, RayHit::Target(target.entity)));
+        }
+    }
+
+    closest.map(|(_, hit)| hit)
+}
+
+/// Finds the corners of a convex brush from its planes, each given as three points on the plane
+///
+/// The points of each plane wind clockwise when seen from outside of the brush
+pub fn brush_vertices(planes: &[[Point3<f32>; 3]]) -> Vec<Point3<f32>> {
+    let planes: Vec<(Vector3<f32>, f32)> = planes
+        .iter()
+        .filter_map(|[a, b, c]| {
+            let normal = (a - b).cross(&(c - b)).try_normalize(f32::EPSILON)?;
+            Some((normal, normal.dot(&b.coords)))
+        })
+        .collect();
+
+    // Every corner lies where three planes meet and behind every other plane
+    let mut vertices = vec![];
+    for i in 0..planes.len() {
+        for j in i + 1..planes.len() {
+            for k in j + 1..planes.len() {
+                let (n1, d1) = planes[i];
+                let (n2, d2) = planes[j];
+                let (n3, d3) = planes[k];
+
+                let denominator = n1.dot(&n2.cross(&n3));
+                if denominator.abs() <= f32::EPSILON {
+                    continue;
+                }
+
+                let vertex = Point3::from(
+                    (n2.cross(&n3).scale(d1) + n3.cross(&n1).scale(d2) + n1.cross(&n2).scale(d3)) / denominator
+                );
+
+                if planes.iter().all(|(normal, distance)| normal.dot(&vertex.coords) <= distance + PLANE_EPSILON) {
+                    vertices.push(vertex);
+                }
+            }
+        }
+    }
+
+    vertices
+}
@@ -0,0 +1,95 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::addon::Manifest;
+use crate::error::Error;
+use crate::input::InputState;
+
+/// The file extension replays are saved with
+const REPLAY_EXTENSION: &str = "replay";
+
+/// A recorded offline session, with everything needed to play it back tick for tick
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Replay {
+    /// The internal name of the addon that was loaded
+    pub addon: String,
+    /// The map the session started on
+    pub map: String,
+    /// The seed the session's random number generator started with
+    pub seed: u64,
+    pub tick_rate: u32,
+    /// The content the session was played with, which has to match exactly for it to play back the same
+    pub manifest: Manifest,
+    /// The local player's input on every tick, in order
+    pub inputs: Vec<InputState>,
+}
+
+impl Replay {
+    pub fn new(addon: &str, map: &str, seed: u64, tick_rate: u32, manifest: Manifest) -> Self {
+        Self {
+            addon: addon.to_string(),
+            map: map.to_string(),
+            seed,
+            tick_rate,
+            manifest,
+            inputs: vec![],
+        }
+    }
+
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = File::open(path)?;
+        let replay: Self = bincode::deserialize_from(BufReader::new(file))?;
+
+        // A damaged replay shouldn't be able to stop time
+        if replay.tick_rate == 0 {
+            return Err(Error::InvalidTickRate(replay.tick_rate));
+        }
+
+        Ok(replay)
+    }
+
+    /// Adds the next tick's input to the end of the replay
+    pub fn record(&mut self, input: InputState) {
+        self.inputs.push(input);
+    }
+
+    /// Saves the replay into `replays_dir` named after the current time, returning where it went
+    pub fn save<P: AsRef<Path>>(&self, replays_dir: P) -> Result<PathBuf, Error> {
+        std::fs::create_dir_all(&replays_dir)?;
+
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+        let path = replays_dir.as_ref().join(format!("{timestamp}.{REPLAY_EXTENSION}"));
+
+        let file = File::create(&path)?;
+        bincode::serialize_into(BufWriter::new(file), self)?;
+        Ok(path)
+    }
+}
+
+/// Steps through the inputs of a replay one tick at a time
+#[derive(Clone, Debug)]
+pub struct Playback {
+    replay: Replay,
+    tick: usize,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Self {
+        Self { replay, tick: 0 }
+    }
+
+    /// The input for the next tick, or nothing once the replay is over
+    pub fn next_input(&mut self) -> Option<InputState> {
+        let input = self.replay.inputs.get(self.tick).copied();
+        self.tick += 1;
+        input
+    }
+}
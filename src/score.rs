@@ -0,0 +1,6 @@
+/// Tracks how well the players are doing
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct Score {
+    /// The number of entities killed by players
+    pub kills: u32,
+}
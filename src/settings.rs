@@ -0,0 +1,237 @@
+use std::io::BufReader;
+use std::io::BufWriter;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::components::Resolution;
+use crate::error::Error;
+use crate::input::Bindings;
+use crate::input::GamepadSettings;
+use crate::input::MouseSettings;
+
+/// How often we check whether the settings file has been edited
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// The narrowest and widest field of view in degrees, beyond which the projection breaks down
+const MIN_FOV: f32 = 1.0;
+const MAX_FOV: f32 = 179.0;
+
+/// The player's preferences, anything missing from the file is left at its default
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct Settings {
+    pub video: VideoSettings,
+    pub bindings: Bindings,
+    pub mouse: MouseSettings,
+    pub gamepad: GamepadSettings,
+}
+
+impl Settings {
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let file = std::fs::File::open(path)?;
+        let reader = BufReader::new(file);
+        let settings = serde_json::from_reader(reader)?;
+        Ok(settings)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        if let Some(dir) = path.as_ref().parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let file = std::fs::File::create(path)?;
+        let writer = BufWriter::new(file);
+        serde_json::to_writer_pretty(writer, self)?;
+        Ok(())
+    }
+
+    /// Applies `key=value` overrides, where keys are paths like `video.fov` and values are JSON or plain strings,
+    /// then pulls anything unusable back into range
+    pub fn with_overrides(&self, overrides: &[String]) -> Result<Self, Error> {
+        let mut settings = serde_json::to_value(self)?;
+        for setting in overrides {
+            let invalid = || Error::InvalidSettingOverride(setting.clone());
+            let (key, value) = setting.split_once('=').ok_or_else(invalid)?;
+            let field = key
+                .split('.')
+                .try_fold(&mut settings, |settings, field| settings.get_mut(field))
+                .ok_or_else(invalid)?;
+            *field = serde_json::from_str(value).unwrap_or_else(|_| Value::String(value.to_string()));
+        }
+
+        let settings: Self = serde_json::from_value(settings)?;
+        Ok(settings.clamped())
+    }
+
+    /// Pulls every setting that would break the game back into a usable range
+    pub fn clamped(self) -> Self {
+        Self {
+            video: self.video.clamped(),
+            mouse: self.mouse.clamped(),
+            gamepad: self.gamepad.clamped(),
+            ..self
+        }
+    }
+}
+
+/// How the game is drawn
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(default)]
+pub struct VideoSettings {
+    /// The size of the window in pixels, when it's windowed
+    pub width: u32,
+    pub height: u32,
+    pub window_mode: WindowMode,
+    /// The monitor to go fullscreen on by its index, the primary monitor if unset
+    pub monitor: Option<usize>,
+    /// The display mode exclusive fullscreen switches the monitor to, its best if unset
+    pub display_mode: Option<DisplayMode>,
+    pub present_mode: PresentMode,
+    /// Vertical field of view in degrees
+    pub fov: f32,
+}
+
+impl VideoSettings {
+    pub fn resolution(&self) -> Resolution {
+        Resolution { width: self.width, height: self.height }
+    }
+
+    /// Keeps the window at least a pixel across and the field of view somewhere it can be projected
+    pub fn clamped(self) -> Self {
+        let fov = match self.fov.is_nan() {
+            true => Self::default().fov,
+            false => self.fov.clamp(MIN_FOV, MAX_FOV),
+        };
+
+        Self {
+            width: self.width.max(1),
+            height: self.height.max(1),
+            fov,
+            ..self
+        }
+    }
+}
+
+impl Default for VideoSettings {
+    fn default() -> Self {
+        Self {
+            width: 1280,
+            height: 720,
+            window_mode: WindowMode::Windowed,
+            monitor: None,
+            display_mode: None,
+            present_mode: PresentMode::Vsync,
+            fov: 90.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowMode {
+    Windowed,
+    /// A borderless window covering the whole monitor, at whatever resolution the monitor is already using
+    Borderless,
+    /// Takes over the monitor, switching it to the chosen display mode
+    Exclusive,
+}
+
+/// A resolution and refresh rate a monitor can run at
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct DisplayMode {
+    pub width: u32,
+    pub height: u32,
+    /// In hertz
+    pub refresh_rate: u16,
+}
+
+/// How finished frames are handed to the display
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PresentMode {
+    /// Waits for the display before presenting each frame, preventing tearing at the cost of latency
+    Vsync,
+    /// Replaces any frame still waiting for the display, without tearing but with less latency than vsync
+    Mailbox,
+    /// Presents frames as soon as they're finished, which can tear
+    Immediate,
+}
+
+impl From<PresentMode> for wgpu::PresentMode {
+    fn from(from: PresentMode) -> Self {
+        match from {
+            PresentMode::Vsync => Self::Fifo,
+            PresentMode::Mailbox => Self::Mailbox,
+            PresentMode::Immediate => Self::Immediate,
+        }
+    }
+}
+
+/// The settings file in the game directory, which is reloaded whenever it's edited
+pub struct SettingsFile {
+    path: PathBuf,
+    /// Overrides from the command line, applied on every load but never saved
+    overrides: Vec<String>,
+    modified: Option<SystemTime>,
+    last_polled: Instant,
+}
+
+impl SettingsFile {
+    pub fn new<P: AsRef<Path>>(path: P, overrides: Vec<String>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            overrides,
+            modified: None,
+            last_polled: Instant::now(),
+        }
+    }
+
+    /// Loads the settings, writing out the defaults first if there's no file yet
+    pub fn load(&mut self) -> Result<Settings, Error> {
+        if !self.path.is_file() {
+            Settings::default().save(&self.path)?;
+        }
+
+        self.modified = self.modified_time();
+        Settings::from_path(&self.path)?.with_overrides(&self.overrides)
+    }
+
+    /// Changes the settings in the file, leaving out any overrides, without treating it as an edit to reload
+    pub fn update<F: FnOnce(&mut Settings)>(&mut self, change: F) -> Result<(), Error> {
+        let mut settings = match self.path.is_file() {
+            true => Settings::from_path(&self.path)?,
+            false => Settings::default(),
+        };
+        change(&mut settings);
+        settings.save(&self.path)?;
+
+        self.modified = self.modified_time();
+        Ok(())
+    }
+
+    /// Reloads the settings if the file has been edited since we last loaded it
+    pub fn poll(&mut self) -> Option<Result<Settings, Error>> {
+        if self.last_polled.elapsed() < POLL_INTERVAL {
+            return None;
+        }
+        self.last_polled = Instant::now();
+
+        let modified = self.modified_time();
+        if modified.is_none() || modified == self.modified {
+            return None;
+        }
+
+        self.modified = modified;
+        Some(Settings::from_path(&self.path).and_then(|settings| settings.with_overrides(&self.overrides)))
+    }
+
+    fn modified_time(&self) -> Option<SystemTime> {
+        std::fs::metadata(&self.path).and_then(|metadata| metadata.modified()).ok()
+    }
+}
@@ -0,0 +1,174 @@
+use legion::Entity;
+use legion::IntoQuery;
+use legion::World;
+use mappy::Map;
+use nalgebra::Point3;
+use nalgebra::UnitQuaternion;
+use nalgebra::Vector3;
+use nalgebra::point;
+use parry3d::shape::SharedShape;
+use tracing::warn;
+
+use crate::components::AirControl;
+use crate::components::Collider;
+use crate::components::Gravity;
+use crate::components::Grounded;
+use crate::components::Health;
+use crate::components::JumpSpeed;
+use crate::components::Model;
+use crate::components::OnDeath;
+use crate::components::PlayerBrain;
+use crate::components::PlayerSpawn;
+use crate::components::Position;
+use crate::components::Rotation;
+use crate::components::Speed;
+use crate::components::Trigger;
+use crate::components::Velocity;
+use crate::components::WarpZone;
+use crate::components::Weapon;
+use crate::components::ZombieBrain;
+use crate::components::ZombieSpawn;
+use crate::input::InputState;
+use crate::physics;
+
+/// What every player is spawned with
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Loadout {
+    pub model: Option<Model>,
+    pub weapon: Option<Weapon>,
+}
+
+/// The health players spawn with
+pub const PLAYER_HEALTH: f32 = 100.0;
+
+/// Map units per world unit, matching the scale applied in `map.wgsl`
+pub const MAP_SCALE: f32 = 16.0;
+
+/// Converts a point in Z-up map space into Y-up world space, matching `Z_UP_TO_Y_UP` in `map.wgsl`
+pub fn map_to_world(point: Point3<f32>) -> Point3<f32> {
+    point![point.x, point.z, -point.y] / MAP_SCALE
+}
+
+/// Pushes an entity into the world for every map entity we know how to spawn
+pub fn spawn_map_entities(world: &mut World, map: &Map<'_>) {
+    for entity in &map.entities {
+        let class_name = match entity.properties.get("classname") {
+            Some(class_name) => *class_name,
+            None => continue,
+        };
+
+        match class_name {
+            "player_spawn" | "zombie_spawn" => spawn_point(world, class_name, entity),
+            "warp_zone" => spawn_warp_zone(world, entity),
+            _ => (),
+        }
+    }
+}
+
+/// Finds the position of the first player spawn in the world
+pub fn find_player_spawn(world: &World) -> Option<Point3<f32>> {
+    <(&PlayerSpawn, &Position)>::query()
+        .iter(world)
+        .next()
+        .map(|(_, position)| position.0)
+}
+
+/// Pushes a player into the world at `position`, equipped with `loadout`
+pub fn spawn_player(world: &mut World, position: Point3<f32>, loadout: &Loadout) -> Entity {
+    let player = world.push((
+        PlayerBrain,
+        InputState::default(),
+        Position(position),
+        Rotation(UnitQuaternion::identity()),
+        Velocity(Vector3::zeros()),
+        Speed(5.64),
+        JumpSpeed(6.5),
+        AirControl(2.0),
+        Gravity(20.0),
+        Grounded(false),
+        Collider(physics::humanoid_shape()),
+        Health(PLAYER_HEALTH),
+        OnDeath::Ragdoll,
+    ));
+
+    if let Some(mut entry) = world.entry(player) {
+        if let Some(model) = loadout.model {
+            entry.add_component(model);
+        }
+
+        if let Some(weapon) = &loadout.weapon {
+            entry.add_component(weapon.clone());
+        }
+    }
+
+    player
+}
+
+/// The components of a freshly spawned zombie, besides the model it's drawn with
+pub fn zombie(
+    position: Point3<f32>,
+) -> (ZombieBrain, Position, Rotation, Velocity, Speed, Gravity, Grounded, Collider, Health) {
+    (
+        ZombieBrain,
+        Position(position),
+        Rotation(UnitQuaternion::identity()),
+        Velocity(Vector3::zeros()),
+        Speed(3.5),
+        Gravity(20.0),
+        Grounded(false),
+        Collider(physics::humanoid_shape()),
+        Health(50.0),
+    )
+}
+
+fn spawn_point(world: &mut World, class_name: &str, entity: &mappy::Entity<'_>) {
+    let origin = match entity.properties.get("origin").and_then(|origin| parse_origin(origin)) {
+        Some(origin) => map_to_world(origin),
+        None => {
+            warn!("Skipping {class_name} without a valid origin");
+            return;
+        },
+    };
+
+    match class_name {
+        "player_spawn" => { world.push((PlayerSpawn, Position(origin))); },
+        "zombie_spawn" => { world.push((ZombieSpawn, Position(origin))); },
+        _ => unreachable!(),
+    }
+}
+
+// Each brush of a warp zone becomes its own trigger volume
+fn spawn_warp_zone(world: &mut World, entity: &mappy::Entity<'_>) {
+    let destination = match entity.properties.get("destination") {
+        Some(destination) => destination.to_string(),
+        None => {
+            warn!("Skipping warp_zone without a destination");
+            return;
+        },
+    };
+    let requires_use = entity.properties.get("requires_use").map_or(false, |requires_use| *requires_use == "1");
+
+    for brush in &entity.brushes {
+        let planes: Vec<[Point3<f32>; 3]> = brush.planes.iter().map(|plane| plane.points).collect();
+        let vertices: Vec<Point3<f32>> = physics::brush_vertices(&planes)
+            .into_iter()
+            .map(map_to_world)
+            .collect();
+
+        match SharedShape::convex_hull(&vertices) {
+            Some(shape) => {
+                world.push((Trigger(shape), WarpZone { destination: destination.clone(), requires_use }));
+            },
+            None => warn!("Skipping degenerate warp_zone brush leading to {destination}"),
+        }
+    }
+}
+
+// Origins are stored as a space separated "x y z" string
+fn parse_origin(origin: &str) -> Option<Point3<f32>> {
+    let mut components = origin.split_whitespace().map(str::parse::<f32>);
+    let x = components.next()?.ok()?;
+    let y = components.next()?.ok()?;
+    let z = components.next()?.ok()?;
+    Some(point![x, y, z])
+}
@@ -1,20 +1,93 @@
+use legion::Entity;
+use legion::IntoQuery;
+use legion::component;
 use legion::system;
+use legion::systems::CommandBuffer;
+use legion::world::SubWorld;
+use nalgebra::Isometry3;
 use nalgebra::Matrix4;
-use nalgebra::vector;
-use tokio::sync::mpsc::UnboundedSender;
+use nalgebra::Point3;
+use nalgebra::UnitQuaternion;
+use nalgebra::Vector3;
+use parry3d::query::Ray;
+use rand::rngs::StdRng;
+use tracing::info;
 
 use crate::camera::Camera;
+use crate::camera::Cameras;
+use crate::components::AirControl;
+use crate::components::Collider;
+use crate::components::Damage;
+use crate::components::Dead;
+use crate::components::Gravity;
+use crate::components::Grounded;
+use crate::components::Health;
+use crate::components::JumpSpeed;
+use crate::components::LocalPlayer;
 use crate::components::Model;
+use crate::components::OnDeath;
 use crate::components::PlayerBrain;
 use crate::components::Position;
+use crate::components::PreviousPosition;
+use crate::components::PreviousRotation;
+use crate::components::Projectile;
+use crate::components::RemoteInput;
 use crate::components::Rotation;
 use crate::components::Speed;
+use crate::components::Trigger;
 use crate::components::Velocity;
+use crate::components::WarpZone;
+use crate::components::Weapon;
+use crate::components::ZombieBrain;
+use crate::components::ZombieSpawn;
+use crate::events::DamageEvent;
+use crate::events::DeathEvent;
+use crate::events::Events;
 use crate::graphics::Instance;
+use crate::graphics::RenderList;
 use crate::input::InputState;
+use crate::interpolation::RenderTime;
+use crate::interpolation::SnapshotBuffer;
+use crate::level::PendingWarp;
+use crate::movement;
+use crate::physics;
+use crate::physics::MapCollider;
+use crate::physics::RayHit;
+use crate::physics::Target;
+use crate::score::Score;
+use crate::spawner;
+use crate::time::Alpha;
 use crate::time::DeltaTime;
+use crate::waves::WaveController;
+use crate::weapon;
+use crate::weapon::WeaponKind;
 
+/// The height of a player's eyes above their position
+const EYE_HEIGHT: f32 = 1.65;
+
+// Remembers where everything was before this tick moves it, starting with anything new
 #[system(for_each)]
+pub fn store_previous_transforms(
+    entity: &Entity,
+    position: &Position,
+    rotation: &Rotation,
+    previous_position: Option<&mut PreviousPosition>,
+    previous_rotation: Option<&mut PreviousRotation>,
+    commands: &mut CommandBuffer,
+) {
+    match previous_position {
+        Some(previous_position) => previous_position.0 = position.0,
+        None => commands.add_component(*entity, PreviousPosition(position.0)),
+    }
+
+    match previous_rotation {
+        Some(previous_rotation) => previous_rotation.0 = rotation.0,
+        None => commands.add_component(*entity, PreviousRotation(rotation.0)),
+    }
+}
+
+#[system(for_each)]
+#[filter(!component::<Collider>())]
 pub fn update_positions(
     #[resource] delta_time: &DeltaTime,
     position: &mut Position,
@@ -23,40 +96,469 @@ pub fn update_positions(
     position.0 += velocity.0.scale(delta_time.0);
 }
 
+// Living remote players are moved as their input arrives instead
 #[system(for_each)]
+#[filter(!component::<RemoteInput>() | component::<Dead>())]
+pub fn update_colliding_positions(
+    #[resource] delta_time: &DeltaTime,
+    #[resource] map_collider: &MapCollider,
+    collider: &Collider,
+    position: &mut Position,
+    velocity: &mut Velocity,
+    grounded: Option<&mut Grounded>,
+) {
+    physics::move_and_slide(&*map_collider.0, &*collider.0, &mut position.0, &mut velocity.0, delta_time.0);
+
+    if let Some(grounded) = grounded {
+        grounded.0 = physics::is_grounded(&*map_collider.0, &*collider.0, &position.0);
+    }
+}
+
+#[system(for_each)]
+#[filter(!component::<RemoteInput>() | component::<Dead>())]
+pub fn apply_gravity(
+    #[resource] delta_time: &DeltaTime,
+    gravity: &Gravity,
+    velocity: &mut Velocity,
+) {
+    velocity.0.y -= gravity.0 * delta_time.0;
+}
+
+#[system(for_each)]
+#[filter(!component::<Dead>() & !component::<RemoteInput>())]
 pub fn update_player_velocities(
-    #[resource] input: &InputState,
+    #[resource] delta_time: &DeltaTime,
+    input: &InputState,
     velocity: &mut Velocity,
-    player_brain: &PlayerBrain,
+    _player_brain: &PlayerBrain,
     speed: &Speed,
+    jump_speed: &JumpSpeed,
+    air_control: &AirControl,
+    grounded: &Grounded,
 ) {
-    let dir = input.move_direction;
-    velocity.0 = vector![dir.x, 0.0, dir.y].scale(speed.0);
+    movement::steer(input, speed.0, jump_speed.0, air_control.0, grounded.0, &mut velocity.0, delta_time.0);
 }
 
 #[system(for_each)]
-pub fn update_player_camera(
-    #[resource] camera: &mut Camera,
+#[filter(!component::<Dead>())]
+pub fn update_player_rotations(
+    input: &InputState,
     _player_brain: &PlayerBrain,
+    rotation: &mut Rotation,
+) {
+    rotation.0 = input.view_direction;
+}
+
+// Shows networked entities where they were at the render time, in between the snapshots we've received
+//
+// The snapshots are already smooth, so there's nothing left to blend between ticks
+#[system(for_each)]
+pub fn interpolate_snapshots(
+    #[resource] render_time: &RenderTime,
+    buffer: &mut SnapshotBuffer,
+    position: &mut Position,
+    rotation: &mut Rotation,
+    previous_position: Option<&mut PreviousPosition>,
+    previous_rotation: Option<&mut PreviousRotation>,
+) {
+    if let Some((sampled_position, sampled_rotation)) = buffer.sample(render_time.0) {
+        position.0 = sampled_position;
+        rotation.0 = sampled_rotation;
+
+        if let Some(previous_position) = previous_position {
+            previous_position.0 = sampled_position;
+        }
+        if let Some(previous_rotation) = previous_rotation {
+            previous_rotation.0 = sampled_rotation;
+        }
+    }
+}
+
+#[system]
+#[read_component(PlayerBrain)]
+#[read_component(ZombieBrain)]
+#[read_component(Dead)]
+#[read_component(Position)]
+#[read_component(Speed)]
+#[write_component(Rotation)]
+#[write_component(Velocity)]
+pub fn update_zombie_velocities(world: &mut SubWorld) {
+    let players: Vec<Point3<f32>> = <&Position>::query()
+        .filter(component::<PlayerBrain>() & !component::<Dead>())
+        .iter(world)
+        .map(|position| position.0)
+        .collect();
+
+    let mut zombies = <(&ZombieBrain, &Position, &Speed, &mut Rotation, &mut Velocity)>::query();
+    for (_, position, speed, rotation, velocity) in zombies.iter_mut(world) {
+        let nearest = players.iter().min_by(|a, b| {
+            let a = (*a - position.0).norm_squared();
+            let b = (*b - position.0).norm_squared();
+            a.total_cmp(&b)
+        });
+
+        // Head straight for the nearest player along the ground, leaving gravity to the y axis
+        let mut direction = match nearest {
+            Some(player) => player - position.0,
+            None => Vector3::zeros(),
+        };
+        direction.y = 0.0;
+
+        let target = direction.try_normalize(f32::EPSILON).unwrap_or_else(Vector3::zeros).scale(speed.0);
+        velocity.0.x = target.x;
+        velocity.0.z = target.z;
+
+        if target != Vector3::zeros() {
+            rotation.0 = UnitQuaternion::face_towards(&target, &Vector3::y_axis());
+        }
+    }
+}
+
+#[system]
+#[read_component(ZombieBrain)]
+#[read_component(ZombieSpawn)]
+#[read_component(Position)]
+pub fn spawn_zombies(
+    #[resource] delta_time: &DeltaTime,
+    #[resource] waves: &mut WaveController,
+    world: &mut SubWorld,
+    commands: &mut CommandBuffer,
+) {
+    let spawn_points: Vec<Point3<f32>> = <&Position>::query()
+        .filter(component::<ZombieSpawn>())
+        .iter(world)
+        .map(|position| position.0)
+        .collect();
+
+    if spawn_points.is_empty() {
+        return;
+    }
+
+    // The intermission before the next wave only starts counting once the last one is cleared
+    let zombies_alive = <&ZombieBrain>::query().iter(world).next().is_some();
+    if waves.remaining == 0 && zombies_alive {
+        waves.timer = waves.intermission;
+        return;
+    }
+
+    waves.timer -= delta_time.0;
+    if waves.timer > 0.0 {
+        return;
+    }
+
+    if waves.remaining == 0 {
+        let wave = waves.begin_next_wave();
+        info!("Starting wave {wave}");
+    }
+
+    let spawn_point = spawn_points[waves.next_spawn % spawn_points.len()];
+    let zombie = commands.push(spawner::zombie(spawn_point));
+    if let Some(model) = waves.zombie_model {
+        commands.add_component(zombie, model);
+    }
+    waves.next_spawn = (waves.next_spawn + 1) % spawn_points.len();
+    waves.remaining -= 1;
+    waves.timer = waves.spawn_interval;
+}
+
+#[system]
+#[read_component(PlayerBrain)]
+#[read_component(ZombieBrain)]
+#[read_component(Dead)]
+#[read_component(Collider)]
+#[read_component(Position)]
+#[read_component(Rotation)]
+#[read_component(InputState)]
+#[write_component(Weapon)]
+pub fn fire_weapons(
+    #[resource] delta_time: &DeltaTime,
+    #[resource] map_collider: &MapCollider,
+    #[resource] rng: &mut StdRng,
+    #[resource] damage_events: &mut Events<DamageEvent>,
+    world: &mut SubWorld,
+    commands: &mut CommandBuffer,
+) {
+    let mut shots = vec![];
+    let mut shooters = <(Entity, &PlayerBrain, &InputState, &Position, &Rotation, &mut Weapon)>::query()
+        .filter(!component::<Dead>());
+    for (shooter, _, input, position, rotation, weapon) in shooters.iter_mut(world) {
+        weapon.cooldown = (weapon.cooldown - delta_time.0).max(0.0);
+        if weapon.cooldown > 0.0 {
+            continue;
+        }
+
+        // Reload when asked to, or when trying to fire with an empty magazine
+        if let Some(magazine) = weapon.stats.magazine {
+            if (input.reload && weapon.ammo < magazine) || (input.fire && weapon.ammo == 0) {
+                weapon.ammo = magazine;
+                weapon.cooldown = weapon.stats.reload_time;
+                continue;
+            }
+        }
+
+        if !input.fire {
+            continue;
+        }
+
+        weapon.cooldown = 1.0 / weapon.stats.fire_rate;
+        if weapon.stats.magazine.is_some() {
+            weapon.ammo -= 1;
+        }
+
+        let mut origin = position.0;
+        origin.y += EYE_HEIGHT;
+        let direction = weapon::spread_direction(rng, &rotation.0, weapon.stats.spread);
+        shots.push((*shooter, origin, direction, weapon.clone()));
+    }
+
+    if shots.is_empty() {
+        return;
+    }
+
+    let targets = zombie_targets(world);
+    for (shooter, origin, direction, weapon) in shots {
+        match weapon.stats.kind {
+            WeaponKind::Hitscan { range } => {
+                let ray = Ray::new(origin, direction);
+                if let Some(RayHit::Target(target)) = physics::cast_ray(&*map_collider.0, &targets, &ray, range) {
+                    damage_events.send(DamageEvent {
+                        target,
+                        amount: weapon.stats.damage,
+                        source: Some(shooter),
+                    });
+                }
+            },
+            WeaponKind::Projectile { speed, lifetime, .. } => {
+                let projectile = commands.push((
+                    Projectile { owner: shooter, lifetime },
+                    Damage(weapon.stats.damage),
+                    Position(origin),
+                    Rotation(UnitQuaternion::face_towards(&direction, &Vector3::y_axis())),
+                    Velocity(direction.scale(speed)),
+                ));
+
+                if let Some(model) = weapon.projectile_model {
+                    commands.add_component(projectile, model);
+                }
+            },
+        }
+    }
+}
+
+#[system]
+#[read_component(ZombieBrain)]
+#[read_component(Collider)]
+#[read_component(Damage)]
+#[read_component(Position)]
+#[read_component(Velocity)]
+#[write_component(Projectile)]
+pub fn update_projectiles(
+    #[resource] delta_time: &DeltaTime,
+    #[resource] map_collider: &MapCollider,
+    #[resource] damage_events: &mut Events<DamageEvent>,
+    world: &mut SubWorld,
+    commands: &mut CommandBuffer,
+) {
+    let targets = zombie_targets(world);
+
+    // Look ahead along the path the projectile is about to take this frame
+    let mut projectiles = <(Entity, &mut Projectile, &Damage, &Position, &Velocity)>::query();
+    for (entity, projectile, damage, position, velocity) in projectiles.iter_mut(world) {
+        projectile.lifetime -= delta_time.0;
+
+        let speed = velocity.0.norm();
+        let hit = match velocity.0.try_normalize(f32::EPSILON) {
+            Some(direction) => {
+                let ray = Ray::new(position.0, direction);
+                physics::cast_ray(&*map_collider.0, &targets, &ray, speed * delta_time.0)
+            },
+            None => None,
+        };
+
+        match hit {
+            Some(RayHit::Target(target)) => {
+                damage_events.send(DamageEvent {
+                    target,
+                    amount: damage.0,
+                    source: Some(projectile.owner),
+                });
+                commands.remove(*entity);
+            },
+            Some(RayHit::Map) => commands.remove(*entity),
+            None if projectile.lifetime <= 0.0 => commands.remove(*entity),
+            None => (),
+        }
+    }
+}
+
+#[system]
+#[read_component(OnDeath)]
+#[write_component(Health)]
+pub fn apply_damage(
+    #[resource] damage_events: &mut Events<DamageEvent>,
+    #[resource] death_events: &mut Events<DeathEvent>,
+    world: &mut SubWorld,
+    commands: &mut CommandBuffer,
+) {
+    // Deaths only last until the next time damage is applied
+    death_events.clear();
+
+    for event in damage_events.drain() {
+        let mut entry = match world.entry_mut(event.target) {
+            Ok(entry) => entry,
+            Err(_) => continue,
+        };
+
+        let health = match entry.get_component_mut::<Health>() {
+            Ok(health) => health,
+            Err(_) => continue,
+        };
+
+        // Don't kill anything twice
+        if health.0 <= 0.0 {
+            continue;
+        }
+
+        health.0 -= event.amount;
+        if health.0 > 0.0 {
+            continue;
+        }
+
+        match entry.get_component::<OnDeath>().copied().unwrap_or(OnDeath::Despawn) {
+            OnDeath::Despawn => commands.remove(event.target),
+            OnDeath::Ragdoll => commands.add_component(event.target, Dead),
+        }
+
+        death_events.send(DeathEvent { entity: event.target, killer: event.source });
+    }
+}
+
+#[system]
+#[read_component(PlayerBrain)]
+pub fn update_score(
+    #[resource] death_events: &Events<DeathEvent>,
+    #[resource] score: &mut Score,
+    world: &SubWorld,
+) {
+    for event in death_events.iter() {
+        let killed_by_player = event.killer
+            .and_then(|killer| world.entry_ref(killer).ok())
+            .map_or(false, |killer| killer.get_component::<PlayerBrain>().is_ok());
+
+        if killed_by_player && event.killer != Some(event.entity) {
+            score.kills += 1;
+        }
+    }
+}
+
+#[system]
+#[read_component(PlayerBrain)]
+#[read_component(Collider)]
+#[read_component(Position)]
+#[read_component(InputState)]
+#[read_component(Trigger)]
+#[read_component(WarpZone)]
+pub fn update_warp_zones(
+    #[resource] pending_warp: &mut PendingWarp,
+    world: &SubWorld,
+) {
+    let mut players = <(&PlayerBrain, &Position, &Collider, &InputState)>::query();
+    let mut warp_zones = <(&Trigger, &WarpZone)>::query();
+
+    for (_, position, collider, input) in players.iter(world) {
+        let isometry = Isometry3::translation(position.0.x, position.0.y, position.0.z);
+        for (trigger, warp_zone) in warp_zones.iter(world) {
+            if warp_zone.requires_use && !input.interact {
+                continue;
+            }
+
+            let inside = parry3d::query::intersection_test(
+                &isometry,
+                &*collider.0,
+                &Isometry3::identity(),
+                &*trigger.0,
+            );
+
+            if inside.unwrap_or(false) {
+                pending_warp.0 = Some(warp_zone.destination.clone());
+                return;
+            }
+        }
+    }
+}
+
+#[system(for_each)]
+pub fn update_player_camera(
+    #[resource] cameras: &mut Cameras,
+    #[resource] alpha: &Alpha,
+    local_player: &LocalPlayer,
     position: &Position,
     rotation: &Rotation,
+    previous_position: Option<&PreviousPosition>,
+    previous_rotation: Option<&PreviousRotation>,
 ) {
-    let mut position = position.0;
-    position.y += 1.65;
+    // Each local player gets their own camera, made as they join
+    if cameras.0.len() <= local_player.0 {
+        cameras.0.resize(local_player.0 + 1, Camera::default());
+    }
+
+    let (mut position, rotation) =
+        interpolate_transform(alpha, position, rotation, previous_position, previous_rotation);
+    position.y += EYE_HEIGHT;
+    let camera = &mut cameras.0[local_player.0];
     camera.position = position;
-    camera.rotation = rotation.0;
+    camera.rotation = rotation;
 }
 
 #[system(for_each)]
 pub fn render_models(
-    #[resource] send: &UnboundedSender<Instance>,
-    _model: &Model,
+    #[resource] render_list: &mut RenderList,
+    #[resource] alpha: &Alpha,
+    model: &Model,
     position: &Position,
     rotation: &Rotation,
+    previous_position: Option<&PreviousPosition>,
+    previous_rotation: Option<&PreviousRotation>,
 ) {
-    // TODO: unwrapping is a code smell
-    send.send(Instance {
-        model: Matrix4::new_translation(&position.0.coords),
-        normal: rotation.0.into(),
-    }).unwrap();
+    let (position, rotation) =
+        interpolate_transform(alpha, position, rotation, previous_position, previous_rotation);
+
+    render_list.push(model.0, Instance {
+        model: Matrix4::new_translation(&position.coords),
+        normal: rotation.into(),
+    });
+}
+
+// Helper function for finding where an entity is between its previous tick and the current one
+fn interpolate_transform(
+    alpha: &Alpha,
+    position: &Position,
+    rotation: &Rotation,
+    previous_position: Option<&PreviousPosition>,
+    previous_rotation: Option<&PreviousRotation>,
+) -> (Point3<f32>, UnitQuaternion<f32>) {
+    let position = match previous_position {
+        Some(previous) => previous.0 + (position.0 - previous.0).scale(alpha.0),
+        None => position.0,
+    };
+
+    let rotation = match previous_rotation {
+        Some(previous) => previous.0.try_slerp(&rotation.0, alpha.0, f32::EPSILON).unwrap_or(rotation.0),
+        None => rotation.0,
+    };
+
+    (position, rotation)
+}
+
+// Helper function for gathering everything shots can hit
+fn zombie_targets(world: &SubWorld<'_>) -> Vec<Target> {
+    <(Entity, &Position, &Collider)>::query()
+        .filter(component::<ZombieBrain>())
+        .iter(world)
+        .map(|(entity, position, collider)| Target {
+            entity: *entity,
+            position: position.0,
+            shape: collider.0.clone(),
+        })
+        .collect()
 }
@@ -1,11 +1,71 @@
 use std::time::Instant;
 
+/// The most frame time we'll try to catch up on at once, so a long stall doesn't snowball
+const MAX_FRAME_TIME: f32 = 0.25;
+/// The slowest and fastest simulated time may pass relative to real time
+const MIN_TIME_SCALE: f32 = 1.0 / 16.0;
+const MAX_TIME_SCALE: f32 = 4.0;
+
+/// How far the current frame is between the last tick and the next, from 0 to 1
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Alpha(pub f32);
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct DeltaTime(pub f32);
 
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct ElapsedTime(pub f32);
 
+/// Controls how fast simulated time passes, for bullet time, pause menus and stepping through ticks
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TimeControl {
+    /// Seconds of simulation per real second, kept within sensible limits
+    scale: f32,
+    pub paused: bool,
+    step_requested: bool,
+}
+
+impl Default for TimeControl {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            paused: false,
+            step_requested: false,
+        }
+    }
+}
+
+impl TimeControl {
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    /// Runs a single tick the next time the simulation would run, if paused
+    pub fn step(&mut self) {
+        self.step_requested = true;
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Sets the time scale, within sensible limits, ignoring anything that isn't a number
+    pub fn set_scale(&mut self, scale: f32) {
+        if !scale.is_nan() {
+            self.scale = scale.clamp(MIN_TIME_SCALE, MAX_TIME_SCALE);
+        }
+    }
+
+    /// Multiplies the time scale by `factor`, within sensible limits
+    pub fn scale_by(&mut self, factor: f32) {
+        self.set_scale(self.scale * factor);
+    }
+}
+
 pub struct Time {
     start_time: Instant,
     previous_time: Instant,
@@ -29,3 +89,148 @@ impl Time {
         dt
     }
 }
+
+/// Steps the simulation at a constant tick rate, however fast frames are rendered
+pub struct FixedTimestep {
+    delta_time: DeltaTime,
+    accumulator: f32,
+}
+
+impl FixedTimestep {
+    pub fn new(tick_rate: u32) -> Self {
+        Self {
+            delta_time: DeltaTime(1.0 / tick_rate as f32),
+            accumulator: 0.0,
+        }
+    }
+
+    /// The length of every tick
+    pub fn delta_time(&self) -> DeltaTime {
+        self.delta_time
+    }
+
+    /// Adds a frame's worth of time to be simulated, as scaled by `control`
+    ///
+    /// While paused nothing is added, unless a step was requested which adds exactly one tick
+    pub fn accumulate(&mut self, frame_time: DeltaTime, control: &mut TimeControl) {
+        if control.paused {
+            if std::mem::take(&mut control.step_requested) {
+                self.accumulator += self.delta_time.0;
+            }
+            return;
+        }
+
+        control.step_requested = false;
+        self.accumulator += (frame_time.0 * control.scale).min(MAX_FRAME_TIME);
+    }
+
+    /// Takes a tick's worth of time to simulate, if enough has built up
+    pub fn tick(&mut self) -> bool {
+        if self.accumulator < self.delta_time.0 {
+            return false;
+        }
+
+        self.accumulator -= self.delta_time.0;
+        true
+    }
+
+    /// How far the leftover time is into the next tick
+    pub fn alpha(&self) -> Alpha {
+        Alpha(self.accumulator / self.delta_time.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DeltaTime;
+    use super::FixedTimestep;
+    use super::MAX_FRAME_TIME;
+    use super::TimeControl;
+
+    // Eight ticks a second keeps every tick length exact in floating point
+    const TICK_RATE: u32 = 8;
+    const TICK: f32 = 1.0 / TICK_RATE as f32;
+
+    fn run(timestep: &mut FixedTimestep, control: &mut TimeControl, frame_time: f32) -> u32 {
+        timestep.accumulate(DeltaTime(frame_time), control);
+
+        let mut ticks = 0;
+        while timestep.tick() {
+            ticks += 1;
+        }
+        ticks
+    }
+
+    #[test]
+    fn leftover_time_carries_into_the_next_frame() {
+        let mut timestep = FixedTimestep::new(TICK_RATE);
+        let mut control = TimeControl::default();
+
+        assert_eq!(run(&mut timestep, &mut control, TICK * 1.5), 1);
+        assert_eq!(timestep.alpha().0, 0.5);
+
+        assert_eq!(run(&mut timestep, &mut control, TICK * 0.5), 1);
+        assert_eq!(timestep.alpha().0, 0.0);
+    }
+
+    #[test]
+    fn short_frames_run_no_ticks() {
+        let mut timestep = FixedTimestep::new(TICK_RATE);
+        let mut control = TimeControl::default();
+
+        assert_eq!(run(&mut timestep, &mut control, TICK * 0.25), 0);
+        assert_eq!(timestep.alpha().0, 0.25);
+    }
+
+    #[test]
+    fn long_frames_are_capped() {
+        let mut timestep = FixedTimestep::new(TICK_RATE);
+        let mut control = TimeControl::default();
+
+        let most_ticks = (MAX_FRAME_TIME / TICK) as u32;
+        assert_eq!(run(&mut timestep, &mut control, MAX_FRAME_TIME), most_ticks);
+        assert_eq!(run(&mut timestep, &mut control, 10.0), most_ticks);
+    }
+
+    #[test]
+    fn time_scale_stretches_frames() {
+        let mut timestep = FixedTimestep::new(TICK_RATE);
+        let mut control = TimeControl::default();
+
+        control.set_scale(0.5);
+        assert_eq!(run(&mut timestep, &mut control, TICK), 0);
+        assert_eq!(run(&mut timestep, &mut control, TICK), 1);
+
+        control.set_scale(-1.0);
+        assert!(control.scale() > 0.0);
+    }
+
+    #[test]
+    fn pausing_stops_ticks_until_stepped() {
+        let mut timestep = FixedTimestep::new(TICK_RATE);
+        let mut control = TimeControl::default();
+
+        control.pause();
+        assert_eq!(run(&mut timestep, &mut control, TICK * 2.0), 0);
+
+        // A step runs exactly one tick, however long the frame was
+        control.step();
+        assert_eq!(run(&mut timestep, &mut control, TICK * 2.0), 1);
+        assert_eq!(run(&mut timestep, &mut control, TICK * 2.0), 0);
+
+        control.resume();
+        assert_eq!(run(&mut timestep, &mut control, TICK), 1);
+    }
+
+    #[test]
+    fn steps_requested_while_running_are_dropped() {
+        let mut timestep = FixedTimestep::new(TICK_RATE);
+        let mut control = TimeControl::default();
+
+        control.step();
+        assert_eq!(run(&mut timestep, &mut control, 0.0), 0);
+
+        control.pause();
+        assert_eq!(run(&mut timestep, &mut control, 0.0), 0);
+    }
+}
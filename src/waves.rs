@@ -0,0 +1,48 @@
+use crate::components::Model;
+
+/// Drives zombie spawning in waves from the map's `zombie_spawn` points
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WaveController {
+    /// The model zombies are drawn with, if the loaded addons provide one
+    pub zombie_model: Option<Model>,
+    /// The number of zombies spawned in the first wave
+    pub base_count: u32,
+    /// The number of extra zombies spawned with each following wave
+    pub count_growth: u32,
+    /// Seconds between each zombie spawned within a wave
+    pub spawn_interval: f32,
+    /// Seconds of rest after a wave is cleared before the next begins
+    pub intermission: f32,
+    /// The current wave, starting at 0 before the first wave begins
+    pub wave: u32,
+    /// The number of zombies left to spawn this wave
+    pub remaining: u32,
+    /// Seconds until the next zombie spawns, or the next wave begins
+    pub timer: f32,
+    /// The spawn point the next zombie will come from
+    pub next_spawn: usize,
+}
+
+impl WaveController {
+    pub fn new(zombie_model: Option<Model>) -> Self {
+        Self {
+            zombie_model,
+            base_count: 4,
+            count_growth: 2,
+            spawn_interval: 0.75,
+            intermission: 5.0,
+            wave: 0,
+            remaining: 0,
+            timer: 5.0,
+            next_spawn: 0,
+        }
+    }
+
+    /// Moves on to the next wave, returning its number
+    pub fn begin_next_wave(&mut self) -> u32 {
+        self.wave += 1;
+        self.remaining = (self.base_count + self.count_growth * (self.wave - 1)).max(1);
+        self.timer = 0.0;
+        self.wave
+    }
+}
@@ -0,0 +1,80 @@
+use nalgebra::UnitQuaternion;
+use nalgebra::Vector3;
+use rand::Rng;
+use serde::Deserialize;
+use serde::Serialize;
+
+/// The stats of a weapon as defined in an addon
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct WeaponStats {
+    /// Shots fired per second
+    pub fire_rate: f32,
+    /// Damage dealt by each shot that lands
+    pub damage: f32,
+    /// The largest angle in degrees a shot may stray from where it was aimed
+    pub spread: f32,
+    /// Shots fired before the weapon has to be reloaded, never if unset
+    pub magazine: Option<u32>,
+    /// Seconds a reload takes
+    #[serde(default)]
+    pub reload_time: f32,
+    /// How shots travel once fired
+    pub kind: WeaponKind,
+}
+
+impl WeaponStats {
+    /// Describes what's wrong with these stats, if they can't be used as they are
+    pub fn problem(&self) -> Option<&'static str> {
+        if self.fire_rate <= 0.0 {
+            return Some("fire_rate must be above 0");
+        }
+
+        if self.spread < 0.0 {
+            return Some("spread can't be negative");
+        }
+
+        if self.magazine == Some(0) {
+            return Some("magazine must hold at least 1 shot");
+        }
+
+        if self.reload_time < 0.0 {
+            return Some("reload_time can't be negative");
+        }
+
+        None
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WeaponKind {
+    /// Shots hit instantly along a ray
+    Hitscan {
+        /// The furthest distance a shot can hit at
+        range: f32,
+    },
+    /// Shots spawn a projectile that travels through the world
+    Projectile {
+        /// The speed the projectile travels at
+        speed: f32,
+        /// Seconds before a projectile that hasn't hit anything is removed
+        lifetime: f32,
+        /// The internal name of the model the projectile is drawn with
+        model: Option<String>,
+    },
+}
+
+/// Picks a random direction within `spread` degrees of `aim`'s forward direction
+pub fn spread_direction<R: Rng>(rng: &mut R, aim: &UnitQuaternion<f32>, spread: f32) -> Vector3<f32> {
+    let spread = spread.to_radians();
+    let deviation = match spread > 0.0 {
+        true => rng.gen_range(0.0..spread),
+        false => 0.0,
+    };
+    let roll = rng.gen_range(0.0..std::f32::consts::TAU);
+
+    let offset = UnitQuaternion::from_axis_angle(&Vector3::z_axis(), roll)
+        * UnitQuaternion::from_axis_angle(&Vector3::x_axis(), deviation);
+
+    (aim * offset) * Vector3::z()
+}